@@ -0,0 +1,31 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generate the service stubs for `proto/blockstats.proto`.
+///
+/// The messages are written by hand in `src/grpc.rs` so that building doesn't
+/// require `protoc` to be installed.
+#[cfg(feature = "grpc")]
+fn grpc() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let service = Service::builder()
+        .name("BlockStatsService")
+        .package("blockstats")
+        .method(
+            Method::builder()
+                .name("subscribe")
+                .route_name("Subscribe")
+                .input_type("crate::grpc::proto::SubscribeRequest")
+                .output_type("crate::grpc::proto::BlockStats")
+                .codec_path("tonic::codec::ProstCodec")
+                .server_streaming()
+                .build(),
+        )
+        .build();
+
+    Builder::new().build_client(false).compile(&[service]);
+}
@@ -1,24 +1,892 @@
-use clap::Parser;
-use futures::{StreamExt, TryStreamExt};
+use blockstats::{
+    aggregate::{
+        ExtrinsicBuckets, SaturationTracker, SessionReport, SessionTotals, WeightFlamegraph,
+        WindowStats, DEFAULT_REPORT_WINDOW,
+    },
+    BlockStats, BlockStatsError, Config, DisplayConfig, HashFormat, Percent, SizeUnit,
+    StatsSubscriptionBuilder,
+};
+use clap::{CommandFactory, FromArgMatches, Parser};
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+use std::{
+    io::{IsTerminal, Write},
+    time::Duration,
+};
+use subxt::backend::rpc::RpcClient;
 
 /// Subscribe to new blocks of a chain and print stats about each block.
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    /// Read the settings of the subscription from this TOML file.
+    ///
+    /// Flags given on the command line take precedence over the file. Switches that are
+    /// on in the file can be turned off with e.g. `--decode-events=false`.
+    #[cfg(feature = "config")]
+    #[clap(long, value_name = "FILE")]
+    config_file: Option<std::path::PathBuf>,
+    /// The settings of `--config-file` which have no flag.
+    #[clap(skip)]
+    config: Config,
     /// The node to connect to. Needs to be a websocket.
-    #[clap(long, default_value = "ws://localhost:9944/")]
+    ///
+    /// Precedence: this flag, then the `BLOCKSTATS_URL` environment variable, then the
+    /// config file, then the default.
+    #[clap(long, env = blockstats::URL_ENV, default_value = blockstats::DEFAULT_URL)]
     url: String,
+    /// Print the active configuration of the relay chain at this URL before starting.
+    ///
+    /// New blocks are compared against its maximum PoV size.
+    #[clap(long)]
+    relay_url: Option<String>,
+    /// Serve the stats over gRPC on this address instead of printing them.
+    #[cfg(feature = "grpc")]
+    #[clap(long)]
+    grpc: Option<std::net::SocketAddr>,
+    /// Serve the stats as Server-Sent Events on this address instead of printing them.
+    #[cfg(feature = "sse")]
+    #[clap(long)]
+    sse: Option<std::net::SocketAddr>,
+    /// Answer health probes at `/healthz` on this address while following new blocks.
+    #[cfg(feature = "health")]
+    #[clap(long)]
+    health_addr: Option<std::net::SocketAddr>,
+    /// Report the subscription as stalled when no block arrived for this many seconds.
+    #[cfg(feature = "health")]
+    #[clap(long, value_name = "SECS", default_value_t = 60)]
+    health_staleness: u64,
+    /// Only print the weight of new blocks.
+    ///
+    /// Works on nodes that have the `dev_getBlockStats` RPC disabled.
+    #[clap(long)]
+    weight_only: bool,
+    /// Instead of following new blocks print the stats of existing blocks starting at this
+    /// block number.
+    #[clap(long, requires = "to")]
+    from: Option<u32>,
+    /// The last block (inclusive) to print when backfilling with `--from`.
+    #[clap(long, requires = "from")]
+    to: Option<u32>,
+    /// Instead of following new blocks print the stats of the blocks produced during the
+    /// last this many seconds.
+    ///
+    /// The number of blocks is estimated using `--block-time`.
+    #[clap(long, value_name = "SECS", conflicts_with_all = ["from", "once"])]
+    since: Option<u64>,
+    /// Backfill using only the `archive_unstable_*` RPC methods.
+    ///
+    /// For archive nodes that have the legacy RPC methods disabled.
+    #[clap(long, requires = "from")]
+    archive: bool,
+    /// Print the stats of the current best block and exit.
+    #[clap(long, conflicts_with = "from")]
+    once: bool,
+    /// How many blocks to fetch in parallel when backfilling.
+    #[clap(long, default_value_t = blockstats::DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+    /// Additionally print a summary over this many blocks every time as many new blocks
+    /// arrived.
+    #[clap(long)]
+    window: Option<usize>,
+    /// The time between two blocks in seconds. Used to compute per second values.
+    #[clap(long, default_value_t = 12.0)]
+    block_time: f64,
+    /// Before following new blocks print this many of the most recent finalized blocks.
+    ///
+    /// This makes sure the `--window` summary has data right from the start.
+    #[clap(long, default_value_t = 0, conflicts_with_all = ["from", "once"])]
+    warmup: usize,
+    /// Warn about blocks whose stats took longer than this many milliseconds to fetch.
+    #[clap(long, value_name = "MS")]
+    log_slow_blocks: Option<u64>,
+    /// Additionally write all blocks to this Parquet file.
+    #[cfg(feature = "arrow")]
+    #[clap(long)]
+    parquet_file: Option<std::path::PathBuf>,
+    /// Additionally insert all blocks into the `block_stats` table of this SQLite database.
+    #[cfg(feature = "sqlite")]
+    #[clap(long)]
+    sqlite_file: Option<std::path::PathBuf>,
+    /// Draw the fill level of all blocks into this PNG file when exiting.
+    #[cfg(feature = "plot")]
+    #[clap(long)]
+    plot_file: Option<std::path::PathBuf>,
+    /// Record the distribution of all blocks into this JSON file when exiting.
+    ///
+    /// Pass it to `--diff-baseline` on a later run to detect regressions.
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "FILE")]
+    record_baseline: Option<std::path::PathBuf>,
+    /// Write the raw inputs of every block to this file.
+    ///
+    /// The stats can be computed from it again with `blockstats::capture::recompute`.
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "FILE")]
+    capture_raw: Option<std::path::PathBuf>,
+    /// Compare every block against a baseline recorded with `--record-baseline`.
+    ///
+    /// Blocks are compared against the 95th percentile of baseline blocks with a similar
+    /// number of extrinsics.
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "FILE")]
+    diff_baseline: Option<std::path::PathBuf>,
+    /// How much higher than the baseline a metric may be before it is flagged.
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "PERCENT", default_value_t = 20.0)]
+    regression_tolerance: f64,
+    /// Additionally produce all blocks as JSON to this Kafka topic.
+    #[cfg(feature = "kafka")]
+    #[clap(long, requires = "kafka_brokers")]
+    kafka_topic: Option<String>,
+    /// Comma separated list of Kafka brokers to use with `--kafka-topic`.
+    #[cfg(feature = "kafka")]
+    #[clap(long, requires = "kafka_topic")]
+    kafka_brokers: Option<String>,
+    /// Check that the storage items read exist with the expected types before following
+    /// new blocks.
+    #[clap(
+        long,
+        action = clap::ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false
+    )]
+    validate_metadata: bool,
+    /// Decode the events of each new block to tell apart blocks that only contain
+    /// inherents in the summary.
+    #[clap(
+        long,
+        action = clap::ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false
+    )]
+    decode_events: bool,
+    /// Count the UMP, DMP and HRMP messages of each new block and print them.
+    #[clap(
+        long,
+        action = clap::ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false
+    )]
+    count_messages: bool,
+    /// Print how many blocks each new block is ahead of the latest finalized block.
+    ///
+    /// Needs a second subscription to the finalized blocks.
+    #[clap(
+        long,
+        action = clap::ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false
+    )]
+    track_finality: bool,
+    /// Highlight blocks whose PoV uses more than this percentage of the maximum PoV size.
+    ///
+    /// The blocks are still printed, just marked as over the budget. Applies to new
+    /// blocks and backfills alike.
+    #[clap(long, value_name = "PERCENT")]
+    soft_pov_budget: Option<f64>,
+    /// Warn when the block length reported by the node doesn't match the encoded block.
+    ///
+    /// Downloads every block in full.
+    #[clap(
+        long,
+        action = clap::ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false
+    )]
+    verify_block_len: bool,
+    /// Keep retrying to connect to the node for this many seconds before giving up when
+    /// following new blocks.
+    #[clap(long, value_name = "SECS", default_value_t = 0)]
+    wait_for_node: u64,
+    /// Exit with an error once the capacity score of `--threshold-blocks` consecutive
+    /// blocks is at or above this percentage.
+    ///
+    /// Useful to fail a load test in CI when the chain is saturated.
+    #[clap(long, value_name = "PERCENT")]
+    threshold_exit: Option<f64>,
+    /// How many consecutive blocks need to be above `--threshold-exit` to fail.
+    #[clap(
+        long,
+        value_name = "K",
+        default_value_t = 1,
+        requires = "threshold_exit"
+    )]
+    threshold_blocks: usize,
+    /// Point out in the summary when the average capacity score of all blocks stayed
+    /// below this percentage.
+    #[clap(long, value_name = "PERCENT", default_value_t = 5.0)]
+    idle_floor: f64,
+    /// Report in the summary how long the capacity score was at or above this percentage.
+    #[clap(long, value_name = "PERCENT", default_value_t = 90.0)]
+    saturation_threshold: f64,
+    /// Only report the saturation of the blocks produced during this many most recent
+    /// seconds instead of the whole run.
+    #[clap(long, value_name = "SECS")]
+    saturation_window: Option<u64>,
+    /// Print the average PoV size and weight of blocks grouped by their number of
+    /// extrinsics when exiting.
+    #[clap(long)]
+    by_extrinsics: bool,
+    /// Print a digest of the hashes of all blocks seen when exiting.
+    ///
+    /// Two runs that observed the same blocks in the same order print the same digest.
+    /// New blocks are followed at the best head, which can differ between nodes until
+    /// it is finalized. Compare digests of `--from`/`--to` ranges of finalized blocks to
+    /// be sure that they agree.
+    #[clap(long)]
+    chain_digest: bool,
+    /// Write a markdown report of all blocks to this file when exiting.
+    ///
+    /// Its windows table has a row for every `--window` blocks.
+    #[clap(long, value_name = "FILE")]
+    report_file: Option<std::path::PathBuf>,
+    /// Write the weight used by each call in the folded stack format of flamegraph tools
+    /// to this file when exiting.
+    ///
+    /// Implies `--decode-events`.
+    #[clap(long, value_name = "FILE")]
+    flamegraph_file: Option<std::path::PathBuf>,
+    /// Additionally print how much PoV size and weight is left for each block.
+    #[clap(long)]
+    show_remaining: bool,
+    /// Which limit the weight percentages of each block are relative to.
+    #[clap(long, value_enum, default_value_t = WeightLimit::Max)]
+    against: WeightLimit,
+    /// Color each block by how close it is to its limits.
+    ///
+    /// `auto` colors only when printing to a terminal and `NO_COLOR` isn't set.
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// How to print the blocks.
+    #[clap(long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+    /// The unit of all sizes. Sums are shown in the next larger unit.
+    #[clap(long, value_enum, default_value_t = Unit::Kib)]
+    size_unit: Unit,
+    /// Number of decimal places of all percentages.
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    percent_decimals: usize,
+    /// Show the hash of every block.
+    #[clap(long, value_enum)]
+    show_hash: Option<HashChoice>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Unit {
+    Bytes,
+    Kib,
+    Mib,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum HashChoice {
+    /// Only the first and last bytes.
+    Abbreviated,
+    Full,
+}
+
+impl Args {
+    /// Parse the command line and fill in what isn't given from `--config-file`.
+    fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let matches = Self::command().get_matches();
+        let args = Self::from_arg_matches(&matches)?;
+        #[cfg(feature = "config")]
+        let args = match &args.config_file {
+            Some(path) => {
+                let config = Config::load(path)?;
+                args.merged(config, &matches)
+            }
+            None => args,
+        };
+        Ok(args)
+    }
+
+    /// Use the settings of `config` for everything not set on the command line.
+    #[cfg(feature = "config")]
+    fn merged(mut self, mut config: Config, matches: &clap::ArgMatches) -> Self {
+        let defaulted =
+            |id| matches.value_source(id) == Some(clap::parser::ValueSource::DefaultValue);
+        if let Some(url) = config.url.take().filter(|_| defaulted("url")) {
+            self.url = url;
+        }
+        if let Some(secs) = config
+            .wait_for_node
+            .take()
+            .filter(|_| defaulted("wait_for_node"))
+        {
+            self.wait_for_node = secs;
+        }
+        self.relay_url = self.relay_url.take().or(config.relay_url.take());
+        self.soft_pov_budget = self.soft_pov_budget.or(config.soft_pov_budget.take());
+        // Switches can be turned off on the command line with e.g. `--decode-events=false`.
+        if defaulted("validate_metadata") {
+            self.validate_metadata = config.validate_metadata;
+        }
+        if defaulted("decode_events") {
+            self.decode_events = config.decode_events;
+        }
+        if defaulted("count_messages") {
+            self.count_messages = config.count_messages;
+        }
+        if defaulted("verify_block_len") {
+            self.verify_block_len = config.verify_block_len;
+        }
+        if defaulted("track_finality") {
+            self.track_finality = config.track_finality;
+        }
+        self.config = config;
+        self
+    }
+
+    /// The settings of the subscription.
+    fn subscription_config(&self) -> Config {
+        Config {
+            url: Some(self.url.clone()),
+            relay_url: self.relay_url.clone(),
+            wait_for_node: Some(self.wait_for_node),
+            validate_metadata: self.validate_metadata,
+            decode_events: self.decode_events || self.flamegraph_file.is_some(),
+            count_messages: self.count_messages,
+            verify_block_len: self.verify_block_len,
+            track_finality: self.track_finality,
+            soft_pov_budget: self.soft_pov_budget,
+            ..self.config.clone()
+        }
+    }
+
+    fn display_config(&self) -> DisplayConfig {
+        DisplayConfig {
+            size_unit: match self.size_unit {
+                Unit::Bytes => SizeUnit::Bytes,
+                Unit::Kib => SizeUnit::KiB,
+                Unit::Mib => SizeUnit::MiB,
+            },
+            percent_decimals: self.percent_decimals,
+            hash: match self.show_hash {
+                None => HashFormat::Hidden,
+                Some(HashChoice::Abbreviated) => HashFormat::Abbreviated,
+                Some(HashChoice::Full) => HashFormat::Full,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Format {
+    /// One line per block.
+    Plain,
+    /// Keep overwriting a single line with the latest block.
+    ///
+    /// The line is cut to the width of the terminal as given by `COLUMNS`, or 80
+    /// columns if that isn't set. Falls back to `plain` when not printing to a terminal.
+    Live,
+}
+
+impl Format {
+    fn live(self) -> bool {
+        matches!(self, Self::Live) && std::io::stdout().is_terminal()
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                std::env::var_os("NO_COLOR").map_or(true, |val| val.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum WeightLimit {
+    /// The maximum weight of the whole block.
+    Max,
+    /// The maximum weight normal extrinsics may use.
+    ///
+    /// Only the weight of the normal class is compared against it. Falls back to the
+    /// block's maximum if the normal class has no limit of its own.
+    Normal,
+}
+
+impl WeightLimit {
+    /// `stat` with `weight` and `max_weight` replaced by the selected ones.
+    fn apply(self, stat: &BlockStats) -> BlockStats {
+        let mut stat = stat.clone();
+        if let Self::Normal = self {
+            stat.weight = stat.class_weight.normal;
+            if let Some(max) = stat.class_limits.normal.max_total {
+                stat.max_weight = max;
+            }
+        }
+        stat
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    let args = Args::load()?;
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    if let Some(relay_url) = &args.relay_url {
+        let relay_client = RpcClient::from_url(relay_url).await?;
+        println!("{}", blockstats::fetch_relay_config(relay_client).await?);
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc {
+        let stats = subscribe(&args).await?;
+        blockstats::grpc::serve_grpc(stats, addr).await?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "sse")]
+    if let Some(addr) = args.sse {
+        let stats = subscribe(&args).await?;
+        blockstats::sse::serve_sse(stats, addr).await?;
+        return Ok(());
+    }
+
+    if args.weight_only {
+        let display = args.display_config();
+        let mut stats = blockstats::subscribe_weight_stats(&args.url)
+            .await?
+            .into_stream()
+            .take_until(Box::pin(tokio::signal::ctrl_c()));
+        while let Some(stat) = stats.next().await {
+            println!("{}", stat?.fmt_with(&display));
+        }
+        return Ok(());
+    }
+
+    if args.once {
+        // Printed like new blocks so that both modes show the same line for a block.
+        let stat = builder(&args).fetch_block(None).await?;
+        println!("{}", block_line(&args, &args.display_config(), &stat));
+        return Ok(());
+    }
+
+    if let (Some(from), Some(to)) = (args.from, args.to) {
+        if args.archive {
+            let rpc_client = RpcClient::from_url(&args.url).await?;
+            let stats =
+                blockstats::stats_for_range_with_archive(rpc_client, from..=to, args.concurrency)
+                    .await?;
+            return print_stats(&args, stats).await;
+        }
+        let stats = blockstats::stats_for_range(&args.url, from..=to, args.concurrency).await?;
+        return print_stats(&args, stats).await;
+    }
 
-    let mut stats = blockstats::subscribe_stats(&args.url).await?.into_stream();
+    if let Some(since) = args.since {
+        let rpc_client = RpcClient::from_url(&args.url).await?;
+        let stats = blockstats::stats_since(
+            rpc_client,
+            Duration::from_secs(since),
+            Duration::from_secs_f64(args.block_time),
+        )
+        .await?;
+        return print_stats(&args, stats).await;
+    }
+
+    let warmup = match args.warmup {
+        0 => Vec::new(),
+        count => blockstats::stats_for_last_finalized(&args.url, count).await?,
+    };
+    let stats = subscribe(&args).await?;
+    let live = after_warmup(&args, warmup.last().map(|stat| stat.number), stats);
+    print_stats(&args, stream::iter(warmup.into_iter().map(Ok)).chain(live)).await
+}
+
+/// The blocks of `stats` that follow the `--warmup` blocks up to `last`.
+///
+/// The warmup ends at the finalized head while new blocks may start ahead of it, or
+/// behind it if they are finalized blocks too. The blocks already printed are skipped
+/// and the gap to the first new block is filled, so that no block is shown twice or
+/// missed.
+fn after_warmup(
+    args: &Args,
+    last: Option<u32>,
+    stats: impl TryStream<Ok = BlockStats, Error = BlockStatsError> + Unpin + Send + 'static,
+) -> impl futures::Stream<Item = Result<BlockStats, BlockStatsError>> + Unpin {
+    let url = args.url.clone();
+    let concurrency = args.concurrency;
+    let mut live = stats.into_stream().try_filter(move |stat| {
+        futures::future::ready(last.map_or(true, |last| stat.number > last))
+    });
+    stream::once(async move {
+        let first = live.try_next().await?;
+        let gap = match (last, &first) {
+            (Some(last), Some(first)) if first.number > last + 1 => {
+                blockstats::stats_for_range(&url, last + 1..=first.number - 1, concurrency)
+                    .await?
+                    .into_stream()
+                    .boxed()
+            }
+            _ => stream::empty().boxed(),
+        };
+        Ok::<_, BlockStatsError>(gap.chain(stream::iter(first.map(Ok))).chain(live))
+    })
+    .try_flatten()
+    .boxed()
+}
+
+/// The subscription settings of `args`, also used for single blocks.
+fn builder(args: &Args) -> StatsSubscriptionBuilder {
+    let builder = args.subscription_config().builder();
+    #[cfg(feature = "serde")]
+    let builder = match &args.capture_raw {
+        Some(path) => builder.capture_raw(path),
+        None => builder,
+    };
+    builder
+}
+
+/// Follow new blocks as configured by `args`.
+async fn subscribe(
+    args: &Args,
+) -> Result<impl TryStream<Ok = BlockStats, Error = BlockStatsError> + Unpin, BlockStatsError> {
+    let stats = builder(args)
+        // Logged to stderr as a line on stdout would break the live and piped output.
+        .on_runtime_upgrade(|upgrade| {
+            tracing::info!(
+                "Runtime upgrade at {:04}: spec_version {} -> {}",
+                upgrade.number,
+                upgrade.previous_spec_version,
+                upgrade.spec_version
+            )
+        })
+        .subscribe()
+        .await?;
+    #[cfg(feature = "health")]
+    let stats = match args.health_addr {
+        Some(addr) => {
+            let liveness =
+                blockstats::health::Liveness::new(Duration::from_secs(args.health_staleness));
+            let probes = blockstats::health::serve_health(liveness.clone(), addr);
+            tokio::spawn(async move {
+                if let Err(err) = probes.await {
+                    tracing::error!("Serving health probes failed: {err}");
+                }
+            });
+            liveness.track(stats).into_stream().boxed()
+        }
+        None => stats.into_stream().boxed(),
+    };
+    Ok(stats)
+}
+
+async fn print_stats(
+    args: &Args,
+    stats: impl TryStream<Ok = BlockStats, Error = BlockStatsError> + Unpin,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Stop on Ctrl-C so that the summaries and output files are written.
+    let mut stats = stats
+        .into_stream()
+        .take_until(Box::pin(tokio::signal::ctrl_c()));
+    let block_interval = Duration::from_secs_f64(args.block_time);
+    let mut window = args
+        .window
+        .map(|size| (size.max(1), WindowStats::new(size, block_interval)));
+    let mut totals = SessionTotals::default();
+    let mut report = args.report_file.as_ref().map(|_| {
+        let window = args.window.unwrap_or(DEFAULT_REPORT_WINDOW);
+        SessionReport::new(&args.url, window, block_interval)
+    });
+    let mut flamegraph = args
+        .flamegraph_file
+        .as_ref()
+        .map(|_| WeightFlamegraph::default());
+    let mut buckets = ExtrinsicBuckets::default();
+    let mut saturation = SaturationTracker::new(
+        Percent::from_percent(args.saturation_threshold),
+        block_interval,
+    );
+    if let Some(secs) = args.saturation_window {
+        saturation = saturation.time_window(Duration::from_secs(secs));
+    }
+    let mut count = 0;
+    let mut congested = 0;
+    let live = args.format.live();
+    let display = args.display_config();
+    #[cfg(feature = "arrow")]
+    let mut parquet = args
+        .parquet_file
+        .as_ref()
+        .map(|path| {
+            blockstats::arrow::ParquetSink::create(path, blockstats::arrow::DEFAULT_ROW_GROUP_SIZE)
+        })
+        .transpose()?;
+    #[cfg(feature = "sqlite")]
+    let mut sqlite = args
+        .sqlite_file
+        .as_ref()
+        .map(|path| {
+            blockstats::sqlite::SqliteSink::open(path, blockstats::sqlite::DEFAULT_BATCH_SIZE)
+        })
+        .transpose()?;
+    #[cfg(feature = "plot")]
+    let mut plot = args.plot_file.as_ref().map(blockstats::plot::PlotSink::new);
+    #[cfg(feature = "serde")]
+    let mut recorder = args
+        .record_baseline
+        .as_ref()
+        .map(|_| blockstats::aggregate::BaselineRecorder::default());
+    #[cfg(feature = "serde")]
+    let baseline = args
+        .diff_baseline
+        .as_ref()
+        .map(
+            |path| -> Result<blockstats::aggregate::Baseline, Box<dyn std::error::Error>> {
+                let file = std::io::BufReader::new(std::fs::File::open(path)?);
+                Ok(serde_json::from_reader(file)?)
+            },
+        )
+        .transpose()?;
+    #[cfg(feature = "serde")]
+    let mut regressed = 0;
+    #[cfg(feature = "kafka")]
+    let kafka = args
+        .kafka_topic
+        .as_ref()
+        .zip(args.kafka_brokers.as_ref())
+        .map(|(topic, brokers)| blockstats::kafka::KafkaSink::new(brokers, topic))
+        .transpose()?;
 
     while let Some(stat) = stats.next().await {
-        println!("{}", stat?);
+        let stat = stat?;
+        if count == 0 {
+            if let Some(para_id) = stat.para_id {
+                println!("Parachain ID: {para_id}");
+            }
+        }
+        if let Some(threshold) = args.log_slow_blocks.map(Duration::from_millis) {
+            if stat.fetch_duration > threshold {
+                tracing::warn!(
+                    "Fetching block #{} took {:?}.",
+                    stat.number,
+                    stat.fetch_duration
+                );
+            }
+        }
+        let line = block_line(args, &display, &stat);
+        if live {
+            // Erase what is left of a previous longer line.
+            print!("\r{line}\x1b[K");
+            std::io::stdout().flush()?;
+        } else {
+            println!("{}", line);
+        }
+        totals.push(&stat);
+        if let Some(flamegraph) = &mut flamegraph {
+            flamegraph.push(&stat);
+        }
+        if let Some(report) = &mut report {
+            report.push(&stat);
+        }
+        buckets.push(&stat);
+        saturation.push(&stat);
+        #[cfg(feature = "arrow")]
+        if let Some(parquet) = &mut parquet {
+            parquet.push(&stat)?;
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlite) = &mut sqlite {
+            sqlite.push(&stat)?;
+        }
+        #[cfg(feature = "plot")]
+        if let Some(plot) = &mut plot {
+            plot.push(&stat);
+        }
+        #[cfg(feature = "kafka")]
+        if let Some(kafka) = &kafka {
+            kafka.send(&stat).await?;
+        }
+        #[cfg(feature = "serde")]
+        if let Some(recorder) = &mut recorder {
+            recorder.push(&stat);
+        }
+        #[cfg(feature = "serde")]
+        if let Some(baseline) = &baseline {
+            let tolerance = Percent::from_percent(args.regression_tolerance);
+            let regressions = baseline.compare(&stat, tolerance);
+            for regression in &regressions {
+                tracing::warn!("Block #{} regressed: {regression}.", stat.number);
+            }
+            if !regressions.is_empty() {
+                regressed += 1;
+            }
+        }
+        count += 1;
+        match args.threshold_exit {
+            Some(threshold) if stat.capacity_score().as_percent() >= threshold => congested += 1,
+            _ => congested = 0,
+        }
+        if let Some((size, window)) = &mut window {
+            window.push(stat);
+            if count % *size == 0 {
+                if live {
+                    println!();
+                }
+                println!("{}", window.fmt_with(&display));
+            }
+        }
+        if congested >= args.threshold_blocks.max(1) {
+            break;
+        }
+    }
+
+    if live && count > 0 {
+        println!();
+    }
+    println!("{}", totals.fmt_with(&display));
+    println!("{saturation}");
+    if args.by_extrinsics {
+        println!("{}", buckets.fmt_with(&display));
+    }
+    if let (Some(path), Some(report)) = (&args.report_file, report) {
+        std::fs::write(path, report.to_markdown())?;
+    }
+    if let (Some(path), Some(flamegraph)) = (&args.flamegraph_file, flamegraph) {
+        std::fs::write(path, flamegraph.to_folded())?;
+    }
+    if args.chain_digest {
+        println!(
+            "Chain digest of {} blocks: {:?}",
+            totals.blocks(),
+            totals.chain_digest()
+        );
+    }
+    let floor = Percent::from_percent(args.idle_floor);
+    if totals.is_underused(floor) {
+        println!(
+            "The chain is mostly idle: the average capacity score of {} stayed below {}.",
+            totals.avg_capacity_score(),
+            floor,
+        );
+    }
+    #[cfg(feature = "serde")]
+    if baseline.is_some() {
+        println!("{regressed} of {count} blocks regressed against the baseline.");
+    }
+    #[cfg(feature = "serde")]
+    if let (Some(path), Some(recorder)) = (&args.record_baseline, recorder) {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, &recorder.finish())?;
+    }
+    #[cfg(feature = "arrow")]
+    if let Some(parquet) = parquet {
+        parquet.finish()?;
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite) = sqlite {
+        sqlite.finish()?;
+    }
+    #[cfg(feature = "plot")]
+    if let Some(plot) = plot {
+        plot.finish()?;
+    }
+
+    if let Some(threshold) = args.threshold_exit {
+        if congested >= args.threshold_blocks.max(1) {
+            return Err(format!(
+                "Capacity was at or above {threshold}% for {congested} consecutive blocks."
+            )
+            .into());
+        }
     }
 
     Ok(())
 }
+
+/// Number of columns of the terminal, from `COLUMNS` or 80 if that isn't set.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+        .unwrap_or(80)
+}
+
+/// Shorten `line` so that it fits into `width` columns without touching the last one.
+///
+/// Some terminals already wrap a line that fills the last column.
+fn truncate_to_width(line: &mut String, width: usize) {
+    if let Some((index, _)) = line.char_indices().nth(width.saturating_sub(1)) {
+        line.truncate(index);
+    }
+}
+
+/// The line printed for `stat`, the same for new blocks, backfills and `--once`.
+fn block_line(args: &Args, display: &DisplayConfig, stat: &BlockStats) -> String {
+    let soft_budget = args.soft_pov_budget.map(Percent::from_percent);
+    let shown = args.against.apply(stat);
+    let mut line = shown.fmt_with(display).to_string();
+    if args.show_remaining {
+        let weight = shown.weight_remaining();
+        let unit = display.size_unit;
+        line += &format!(
+            " Remaining PoV Size={:04}{} RefTime={:07}ms ProofSize={:04}{}",
+            unit.scale(shown.pov_remaining()),
+            unit.suffix(),
+            weight.ref_time() / 1_000_000_000,
+            unit.scale(weight.proof_size()),
+            unit.suffix(),
+        );
+    }
+    if let (Some(ump), Some(dmp), Some(hrmp)) =
+        (shown.ump_messages, shown.dmp_messages, shown.hrmp_messages)
+    {
+        line += &format!(" Messages UMP={ump:03} DMP={dmp:03} HRMP={hrmp:03}");
+    }
+    if let Some(finality_lag) = shown.finality_lag {
+        line += &format!(" Finality lag={finality_lag:02}");
+    }
+    // Backfilled blocks weren't compared against the budget by a subscription.
+    let over_budget = shown
+        .over_soft_budget
+        .unwrap_or_else(|| soft_budget.is_some_and(|budget| shown.is_over_soft_budget(budget)));
+    if over_budget {
+        line += " Over soft PoV budget";
+    }
+    if args.format.live() {
+        // A line wrapping around can't be overwritten with a carriage return.
+        truncate_to_width(&mut line, terminal_width());
+    }
+    if args.color.enabled() {
+        line = colorize(&shown, &line, over_budget);
+    }
+    line
+}
+
+/// `line` colored green, yellow or red depending on the most exhausted resource of `stat`.
+fn colorize(stat: &BlockStats, line: &str, over_budget: bool) -> String {
+    let fill = stat.capacity_score().as_percent();
+    let code = if fill < 70.0 {
+        32
+    } else if fill <= 90.0 {
+        33
+    } else {
+        31
+    };
+    // Bold makes blocks over the soft budget stand out within their color.
+    let bold = if over_budget { "1;" } else { "" };
+    format!("\x1b[{bold}{code}m{line}\x1b[0m")
+}
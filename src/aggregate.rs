@@ -0,0 +1,1370 @@
+//! Statistics over multiple blocks.
+//!
+//! Most of the types here keep a fixed amount of data however many blocks they see.
+//! The exceptions grow with the length of a run and are meant for runs of a bounded
+//! length, like a benchmark or a backfill:
+//!
+//! - [`BaselineRecorder`] keeps three values of every block.
+//! - [`SessionReport`] keeps a row for every filled window.
+//! - [`WeightFlamegraph`] keeps a sum for every distinct call, which is bounded by the
+//!   calls of the runtime rather than by the number of blocks.
+
+use crate::{
+    display::FmtWith, fraction, BlockStats, Bytes, DisplayConfig, DisplayWith, Percent, Weight,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    time::Duration,
+};
+use subxt::ext::sp_core::{hashing::blake2_256, H256};
+
+/// Totals over all blocks seen since the start.
+///
+/// All sums are kept as `u128` so that they can't overflow even on very long runs.
+///
+/// Use the custom [`fmt::Display`] implementation to pretty print it.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::SessionTotals, BlockStats, Bytes};
+///
+/// let block = BlockStats { pov_len: Bytes(u64::MAX), ..Default::default() };
+/// let mut totals = SessionTotals::default();
+/// for _ in 0..10_000 {
+///     totals.push(&block);
+/// }
+/// assert_eq!(totals.total_pov_len(), u128::from(u64::MAX) * 10_000);
+/// assert_eq!(totals.avg_pov_len(), Bytes(u64::MAX));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SessionTotals {
+    blocks: u64,
+    pov_len: u128,
+    witness_len: u128,
+    len: u128,
+    ref_time: u128,
+    proof_size: u128,
+    num_extrinsics: u128,
+    max_pov_len: Bytes,
+    idle_blocks: u64,
+    busy_pov_len: u128,
+    busy_ref_time: u128,
+    /// Sum of [`BlockStats::capacity_score`] in percent.
+    capacity_score: f64,
+    chain_digest: H256,
+}
+
+impl SessionTotals {
+    /// Account for a new block.
+    pub fn push(&mut self, stats: &BlockStats) {
+        self.blocks += 1;
+        self.pov_len += u128::from(stats.pov_len);
+        self.witness_len += u128::from(stats.witness_len);
+        self.len += u128::from(stats.len);
+        self.ref_time += u128::from(stats.weight.ref_time);
+        self.proof_size += u128::from(stats.weight.proof_size);
+        self.num_extrinsics += u128::from(stats.num_extrinsics);
+        self.max_pov_len = self.max_pov_len.max(stats.pov_len);
+        self.capacity_score += stats.capacity_score().as_percent();
+        let mut preimage = [0; 64];
+        preimage[..32].copy_from_slice(self.chain_digest.as_bytes());
+        preimage[32..].copy_from_slice(stats.hash.as_bytes());
+        self.chain_digest = H256(blake2_256(&preimage));
+        if stats.is_idle() {
+            self.idle_blocks += 1;
+        } else {
+            self.busy_pov_len += u128::from(stats.pov_len);
+            self.busy_ref_time += u128::from(stats.weight.ref_time);
+        }
+    }
+
+    /// Number of blocks seen.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Sum of the PoV sizes of all blocks in bytes.
+    pub fn total_pov_len(&self) -> u128 {
+        self.pov_len
+    }
+
+    /// Sum of the storage proof sizes of all blocks in bytes.
+    pub fn total_witness_len(&self) -> u128 {
+        self.witness_len
+    }
+
+    /// Sum of the sizes of all blocks in bytes.
+    pub fn total_len(&self) -> u128 {
+        self.len
+    }
+
+    /// Sum of the `ref_time` used by all blocks.
+    pub fn total_ref_time(&self) -> u128 {
+        self.ref_time
+    }
+
+    /// Sum of the `proof_size` used by all blocks.
+    pub fn total_proof_size(&self) -> u128 {
+        self.proof_size
+    }
+
+    /// Number of extrinsics in all blocks.
+    pub fn total_extrinsics(&self) -> u128 {
+        self.num_extrinsics
+    }
+
+    /// Average PoV size.
+    pub fn avg_pov_len(&self) -> Bytes {
+        Bytes(average(self.pov_len, self.blocks as usize))
+    }
+
+    /// Bytes of all blocks per byte of PoV.
+    ///
+    /// Weighted by size unlike averaging [`BlockStats::pov_efficiency`] so that empty
+    /// blocks don't skew the result. Zero if no PoV was seen.
+    pub fn pov_efficiency(&self) -> f64 {
+        if self.pov_len == 0 {
+            return 0.0;
+        }
+        self.len as f64 / self.pov_len as f64
+    }
+
+    /// Largest PoV size.
+    pub fn max_pov_len(&self) -> Bytes {
+        self.max_pov_len
+    }
+
+    /// Average `ref_time` used by a block.
+    pub fn avg_ref_time(&self) -> u64 {
+        average(self.ref_time, self.blocks as usize)
+    }
+
+    /// Number of blocks without any user extrinsics. See [`BlockStats::is_idle`].
+    pub fn idle_blocks(&self) -> u64 {
+        self.idle_blocks
+    }
+
+    /// Average PoV size of the blocks that weren't idle.
+    ///
+    /// Idle blocks drag down the average on bursty chains and hide how full the blocks
+    /// are when there is actual load.
+    pub fn avg_pov_len_excluding_idle(&self) -> Bytes {
+        Bytes(average(self.busy_pov_len, self.busy_blocks()))
+    }
+
+    /// Average `ref_time` used by the blocks that weren't idle.
+    pub fn avg_ref_time_excluding_idle(&self) -> u64 {
+        average(self.busy_ref_time, self.busy_blocks())
+    }
+
+    /// Average [`BlockStats::capacity_score`] of all blocks.
+    pub fn avg_capacity_score(&self) -> Percent {
+        if self.blocks == 0 {
+            return Percent::default();
+        }
+        Percent::from_percent(self.capacity_score / self.blocks as f64)
+    }
+
+    /// A digest of the hashes of all blocks seen in the order they were pushed.
+    ///
+    /// Every block updates it to the BLAKE2b-256 hash of the previous digest followed
+    /// by the block hash, starting from all zeros. Two observers that saw the same
+    /// blocks in the same order end up with the same digest while a single differing,
+    /// missing or reordered block changes it.
+    ///
+    /// The subscriptions follow the best blocks, which can differ between nodes until
+    /// they are finalized. Two observers hence only agree on the digest when they
+    /// follow finalized blocks, e.g. by computing it over a range of finalized blocks
+    /// with [`stats_for_range`](crate::stats_for_range).
+    ///
+    /// ```
+    /// use blockstats::{aggregate::SessionTotals, BlockStats};
+    /// use subxt::ext::sp_core::H256;
+    ///
+    /// let blocks = [H256::repeat_byte(1), H256::repeat_byte(2)]
+    ///     .map(|hash| BlockStats { hash, ..Default::default() });
+    /// let digest = |order: [usize; 2]| {
+    ///     let mut totals = SessionTotals::default();
+    ///     for i in order {
+    ///         totals.push(&blocks[i]);
+    ///     }
+    ///     totals.chain_digest()
+    /// };
+    /// assert_eq!(
+    ///     format!("{:?}", digest([0, 1])),
+    ///     "0x837fd1714d8de39ba4cc0e31d594f478713ef1c98ac29e2fae0685df02983195",
+    /// );
+    /// assert_ne!(digest([0, 1]), digest([1, 0]));
+    /// ```
+    pub fn chain_digest(&self) -> H256 {
+        self.chain_digest
+    }
+
+    /// Whether the blocks were on average filled less than `floor`.
+    ///
+    /// A chain that runs far below its limits for a long time might warrant tuning its
+    /// parameters. Always `false` before the first block.
+    pub fn is_underused(&self, floor: Percent) -> bool {
+        self.blocks > 0 && self.avg_capacity_score() < floor
+    }
+
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
+
+    fn busy_blocks(&self) -> usize {
+        (self.blocks - self.idle_blocks) as usize
+    }
+}
+
+impl fmt::Display for SessionTotals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for SessionTotals {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
+        write!(
+            f,
+            "Total {:04} blocks: PoV Sum={} Avg={} Max={} Witness Sum={} Block Sum={} NumExtrinsics={:06} Excluding {:04} idle blocks: PoV Avg={}",
+            self.blocks,
+            config.sum(self.pov_len),
+            config.size(self.avg_pov_len()),
+            config.size(self.max_pov_len),
+            config.sum(self.witness_len),
+            config.sum(self.len),
+            self.num_extrinsics,
+            self.idle_blocks,
+            config.size(self.avg_pov_len_excluding_idle()),
+        )
+    }
+}
+
+/// Aggregated stats over the most recent blocks.
+///
+/// Sums are kept as `u128` so that averages over large values can't overflow.
+///
+/// Use the custom [`fmt::Display`] implementation to pretty print it.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::WindowStats, BlockStats, Bytes, Weight};
+/// use std::time::Duration;
+///
+/// let half = u64::MAX / 2;
+/// let block = BlockStats {
+///     pov_len: Bytes(half),
+///     len: Bytes(half),
+///     weight: Weight::from_parts(half, half),
+///     ..Default::default()
+/// };
+/// let mut window = WindowStats::new(10_000, Duration::from_secs(6));
+/// for _ in 0..10_000 {
+///     window.push(block.clone());
+/// }
+/// assert_eq!(window.len(), 10_000);
+/// assert_eq!(window.avg_pov_len(), Bytes(half));
+/// assert_eq!(window.max_pov_len(), Bytes(half));
+/// assert_eq!(window.avg_ref_time(), half);
+/// assert_eq!(window.avg_proof_size(), half);
+/// assert_eq!(window.pov_efficiency(), 1.0);
+/// assert_eq!(window.bandwidth_bps(), half as f64 / 6.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WindowStats {
+    size: usize,
+    block_interval: Duration,
+    blocks: VecDeque<BlockStats>,
+    pov_len: SortedValues,
+    ref_time: SortedValues,
+    proof_size: SortedValues,
+}
+
+impl WindowStats {
+    /// Create an empty window which holds up to `size` blocks.
+    ///
+    /// A `size` of zero is treated as one.
+    ///
+    /// `block_interval` is the time between two blocks of the chain. It is used to
+    /// convert per block values into per second values.
+    pub fn new(size: usize, block_interval: Duration) -> Self {
+        Self {
+            size: size.max(1),
+            block_interval,
+            blocks: VecDeque::with_capacity(size.max(1)),
+            pov_len: SortedValues::default(),
+            ref_time: SortedValues::default(),
+            proof_size: SortedValues::default(),
+        }
+    }
+
+    /// Add a block to the window evicting the oldest one if the window is full.
+    pub fn push(&mut self, stats: BlockStats) {
+        if self.blocks.len() == self.size {
+            if let Some(evicted) = self.blocks.pop_front() {
+                self.pov_len.remove(evicted.pov_len.into());
+                self.ref_time.remove(evicted.weight.ref_time);
+                self.proof_size.remove(evicted.weight.proof_size);
+            }
+        }
+        self.pov_len.insert(stats.pov_len.into());
+        self.ref_time.insert(stats.weight.ref_time);
+        self.proof_size.insert(stats.weight.proof_size);
+        self.blocks.push_back(stats);
+    }
+
+    /// The blocks currently in the window. Oldest first.
+    pub fn blocks(&self) -> impl Iterator<Item = &BlockStats> {
+        self.blocks.iter()
+    }
+
+    /// Number of blocks currently in the window.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Whether no block was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Average PoV size.
+    pub fn avg_pov_len(&self) -> Bytes {
+        Bytes(average(self.sum(|stats| stats.pov_len), self.len()))
+    }
+
+    /// Largest PoV size.
+    pub fn max_pov_len(&self) -> Bytes {
+        self.blocks
+            .iter()
+            .map(|stats| stats.pov_len)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Average `ref_time` used by a block.
+    pub fn avg_ref_time(&self) -> u64 {
+        average(self.sum(|stats| stats.weight.ref_time), self.len())
+    }
+
+    /// Average `proof_size` used by a block.
+    pub fn avg_proof_size(&self) -> u64 {
+        average(self.sum(|stats| stats.weight.proof_size), self.len())
+    }
+
+    /// Average of [`BlockStats::pov_percent`].
+    ///
+    /// Every block is relative to its own limit. Zero if the window is empty.
+    pub fn avg_pov_percent(&self) -> Percent {
+        self.avg_percent(BlockStats::pov_percent)
+    }
+
+    /// Largest [`BlockStats::pov_percent`]. Zero if the window is empty.
+    pub fn max_pov_percent(&self) -> Percent {
+        self.blocks
+            .iter()
+            .map(BlockStats::pov_percent)
+            .fold(Percent::default(), Percent::max)
+    }
+
+    /// Average of [`BlockStats::ref_time_percent`].
+    ///
+    /// Every block is relative to its own [`BlockStats::max_weight`] so that this stays
+    /// meaningful when a runtime upgrade within the window changed the weight constants.
+    /// Zero if the window is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blockstats::{aggregate::WindowStats, BlockStats, Weight};
+    /// use std::time::Duration;
+    ///
+    /// let mut window = WindowStats::new(10, Duration::from_secs(12));
+    /// // Half full before the upgrade.
+    /// window.push(BlockStats {
+    ///     weight: Weight::from_parts(500, 0),
+    ///     max_weight: Weight::from_parts(1_000, 0),
+    ///     ..Default::default()
+    /// });
+    /// // Weights became ten times larger after the upgrade but the block is as full.
+    /// window.push(BlockStats {
+    ///     weight: Weight::from_parts(5_000, 0),
+    ///     max_weight: Weight::from_parts(10_000, 0),
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(window.avg_ref_time_percent().as_percent(), 50.0);
+    /// assert_eq!(window.ref_time_percent_percentile(100.0).as_percent(), 50.0);
+    /// ```
+    pub fn avg_ref_time_percent(&self) -> Percent {
+        self.avg_percent(BlockStats::ref_time_percent)
+    }
+
+    /// Average of [`BlockStats::proof_size_percent`].
+    ///
+    /// Every block is relative to its own limit. Zero if the window is empty.
+    pub fn avg_proof_size_percent(&self) -> Percent {
+        self.avg_percent(BlockStats::proof_size_percent)
+    }
+
+    /// The [`BlockStats::ref_time_percent`] that `p` percent of the blocks don't exceed.
+    ///
+    /// `p` is between 0 and 100. Zero if the window is empty.
+    pub fn ref_time_percent_percentile(&self, p: f64) -> Percent {
+        self.percent_percentile(p, BlockStats::ref_time_percent)
+    }
+
+    /// The [`BlockStats::proof_size_percent`] that `p` percent of the blocks don't exceed.
+    ///
+    /// `p` is between 0 and 100. Zero if the window is empty.
+    pub fn proof_size_percent_percentile(&self, p: f64) -> Percent {
+        self.percent_percentile(p, BlockStats::proof_size_percent)
+    }
+
+    /// The PoV size in bytes that `p` percent of the blocks don't exceed.
+    ///
+    /// `p` is between 0 and 100. Zero if the window is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blockstats::{aggregate::WindowStats, BlockStats, Bytes};
+    /// use std::time::Duration;
+    ///
+    /// let mut window = WindowStats::new(100, Duration::from_secs(12));
+    /// for pov_len in 1..=200 {
+    ///     window.push(BlockStats { pov_len: Bytes(pov_len), ..Default::default() });
+    /// }
+    /// assert_eq!(window.pov_len_percentile(50.0), Bytes(150));
+    /// assert_eq!(window.pov_len_percentile(95.0), Bytes(195));
+    /// assert_eq!(window.pov_len_percentile(100.0), Bytes(200));
+    /// ```
+    pub fn pov_len_percentile(&self, p: f64) -> Bytes {
+        Bytes(self.pov_len.percentile(p))
+    }
+
+    /// The `ref_time` that `p` percent of the blocks don't exceed.
+    ///
+    /// `p` is between 0 and 100. Zero if the window is empty.
+    pub fn ref_time_percentile(&self, p: f64) -> u64 {
+        self.ref_time.percentile(p)
+    }
+
+    /// The `proof_size` that `p` percent of the blocks don't exceed.
+    ///
+    /// `p` is between 0 and 100. Zero if the window is empty.
+    pub fn proof_size_percentile(&self, p: f64) -> u64 {
+        self.proof_size.percentile(p)
+    }
+
+    /// Share of the blocks that are [proof bound](BlockStats::proof_bound_first) between
+    /// 0.0 and 1.0.
+    ///
+    /// Values close to 1.0 mean that the storage proof size is the bottleneck rather than
+    /// computation. Zero if the window is empty.
+    pub fn proof_bound_fraction(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let proof_bound = self
+            .blocks
+            .iter()
+            .filter(|stats| stats.proof_bound_first())
+            .count();
+        proof_bound as f64 / self.len() as f64
+    }
+
+    /// Signed extrinsics per second over the window.
+    ///
+    /// Only blocks for which signed extrinsics were counted are considered. `None` if
+    /// there are none. See [`BlockStats::effective_tps`].
+    pub fn effective_tps(&self) -> Option<f64> {
+        let (blocks, signed) = self
+            .blocks
+            .iter()
+            .filter_map(|stats| stats.num_signed)
+            .fold((0_usize, 0_u64), |(blocks, sum), signed| {
+                (blocks + 1, sum + signed)
+            });
+        if blocks == 0 {
+            return None;
+        }
+        let secs = self.block_interval.as_secs_f64() * blocks as f64;
+        if secs == 0.0 {
+            return Some(0.0);
+        }
+        Some(signed as f64 / secs)
+    }
+
+    /// Bytes of all blocks in the window per byte of PoV.
+    ///
+    /// See [`SessionTotals::pov_efficiency`]. Zero if no PoV was seen.
+    pub fn pov_efficiency(&self) -> f64 {
+        let pov_len = self.sum(|stats| stats.pov_len);
+        if pov_len == 0 {
+            return 0.0;
+        }
+        self.sum(|stats| stats.len) as f64 / pov_len as f64
+    }
+
+    /// Estimated bytes per second sent to the relay chain over the window.
+    pub fn bandwidth_bps(&self) -> f64 {
+        let secs = self.block_interval.as_secs_f64() * self.len() as f64;
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.sum(|stats| stats.pov_len) as f64 / secs
+    }
+
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
+
+    fn avg_percent(&self, f: impl Fn(&BlockStats) -> Percent) -> Percent {
+        if self.is_empty() {
+            return Percent::default();
+        }
+        let sum: f64 = self.blocks.iter().map(|stats| f(stats).as_percent()).sum();
+        Percent::from_percent(sum / self.len() as f64)
+    }
+
+    /// Nearest rank percentile like [`SortedValues::percentile`].
+    fn percent_percentile(&self, p: f64, f: impl Fn(&BlockStats) -> Percent) -> Percent {
+        let mut percents: Vec<f64> = self
+            .blocks
+            .iter()
+            .map(|stats| f(stats).as_percent())
+            .collect();
+        percents.sort_by(f64::total_cmp);
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * percents.len() as f64).ceil() as usize;
+        percents
+            .get(rank.saturating_sub(1))
+            .copied()
+            .map(Percent::from_percent)
+            .unwrap_or_default()
+    }
+
+    /// Summed up as `u128` so that it can't overflow.
+    fn sum<T: Into<u128>>(&self, f: impl Fn(&BlockStats) -> T) -> u128 {
+        self.blocks.iter().map(|stats| f(stats).into()).sum()
+    }
+}
+
+impl fmt::Display for WindowStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for WindowStats {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
+        // Percentages are computed per block as the limits can change within the window.
+        write!(
+            f,
+            "Last {:04} blocks: PoV Avg={}({}%) Max={}({}%) Weight Avg RefTime={}% ProofSize={}% Bandwidth={}/s PoV P50={} P95={} P99={} Weight P95 RefTime={}% ProofSize={}% ProofBound={}%",
+            self.len(),
+            config.size(self.avg_pov_len()),
+            config.percent(self.avg_pov_percent()),
+            config.size(self.max_pov_len()),
+            config.percent(self.max_pov_percent()),
+            config.percent(self.avg_ref_time_percent()),
+            config.percent(self.avg_proof_size_percent()),
+            config.size(self.bandwidth_bps() as u64),
+            config.size(self.pov_len_percentile(50.0)),
+            config.size(self.pov_len_percentile(95.0)),
+            config.size(self.pov_len_percentile(99.0)),
+            config.percent(self.ref_time_percent_percentile(95.0)),
+            config.percent(self.proof_size_percent_percentile(95.0)),
+            config.fine_percent(Percent::from_ratio(self.proof_bound_fraction())),
+        )
+    }
+}
+
+/// How long the chain was saturated, i.e. at or above a capacity score threshold.
+///
+/// Either covers all blocks or only the most recent ones, see
+/// [`SaturationTracker::window`]. The wall-clock time is estimated from the number of
+/// saturated blocks and the block interval. Use the custom [`fmt::Display`]
+/// implementation to pretty print it.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::SaturationTracker, BlockStats, Bytes, Percent};
+/// use std::time::Duration;
+///
+/// let mut tracker = SaturationTracker::new(Percent::from_percent(90.0), Duration::from_secs(6));
+/// for pov_len in [95, 50, 91, 100] {
+///     tracker.push(&BlockStats { pov_len: Bytes(pov_len), max_pov: Bytes(100), ..Default::default() });
+/// }
+/// assert_eq!(tracker.saturated_blocks(), 3);
+/// assert_eq!(tracker.saturated_duration(), Duration::from_secs(18));
+/// assert_eq!(tracker.to_string(), "Time above 90%: 18s (75% of run)");
+/// ```
+#[derive(Clone, Debug)]
+pub struct SaturationTracker {
+    threshold: Percent,
+    block_interval: Duration,
+    /// Maximum number of blocks taken into account. `None` for all blocks.
+    window: Option<usize>,
+    /// Whether each of the blocks in the window was saturated. Only kept with a window.
+    recent: VecDeque<bool>,
+    blocks: u64,
+    saturated: u64,
+}
+
+impl SaturationTracker {
+    /// Count blocks whose [`BlockStats::capacity_score`] is at or above `threshold`.
+    ///
+    /// `block_interval` is the time between two blocks of the chain.
+    pub fn new(threshold: Percent, block_interval: Duration) -> Self {
+        Self {
+            threshold,
+            block_interval,
+            window: None,
+            recent: VecDeque::new(),
+            blocks: 0,
+            saturated: 0,
+        }
+    }
+
+    /// Only take the most recent `size` blocks into account.
+    ///
+    /// A `size` of zero is treated as one.
+    pub fn window(mut self, size: usize) -> Self {
+        self.window = Some(size.max(1));
+        self
+    }
+
+    /// Only take the blocks of the most recent `duration` into account.
+    ///
+    /// The number of blocks is estimated from the block interval.
+    pub fn time_window(self, duration: Duration) -> Self {
+        let secs = self.block_interval.as_secs_f64();
+        let size = if secs > 0.0 {
+            (duration.as_secs_f64() / secs).ceil() as usize
+        } else {
+            1
+        };
+        self.window(size)
+    }
+
+    /// Account for a new block evicting the oldest one if the window is full.
+    pub fn push(&mut self, stats: &BlockStats) {
+        let saturated = stats.capacity_score() >= self.threshold;
+        if let Some(size) = self.window {
+            if self.recent.len() == size {
+                if self.recent.pop_front() == Some(true) {
+                    self.saturated -= 1;
+                }
+                self.blocks -= 1;
+            }
+            self.recent.push_back(saturated);
+        }
+        self.blocks += 1;
+        self.saturated += u64::from(saturated);
+    }
+
+    /// Number of blocks taken into account.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Number of blocks at or above the threshold.
+    pub fn saturated_blocks(&self) -> u64 {
+        self.saturated
+    }
+
+    /// Fraction of the blocks at or above the threshold. Zero before the first block.
+    pub fn saturated_fraction(&self) -> Percent {
+        Percent::from_ratio(fraction(self.saturated, self.blocks))
+    }
+
+    /// Estimated wall-clock time spent at or above the threshold.
+    pub fn saturated_duration(&self) -> Duration {
+        self.block_interval
+            .saturating_mul(self.saturated.try_into().unwrap_or(u32::MAX))
+    }
+}
+
+impl fmt::Display for SaturationTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Time above {}%: {} ({:.0}% of ",
+            self.threshold.as_percent(),
+            HumanDuration(self.saturated_duration()),
+            self.saturated_fraction().as_percent(),
+        )?;
+        match self.window {
+            Some(size) => write!(f, "last {size} blocks)"),
+            None => write!(f, "run)"),
+        }
+    }
+}
+
+/// Displays a duration like `1h2m3s` with whole seconds.
+struct HumanDuration(Duration);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+        if hours > 0 {
+            write!(f, "{hours}h{minutes}m{secs}s")
+        } else if minutes > 0 {
+            write!(f, "{minutes}m{secs}s")
+        } else {
+            write!(f, "{secs}s")
+        }
+    }
+}
+
+/// The upper bounds (inclusive) used by [`ExtrinsicBuckets::default`].
+pub const DEFAULT_EXTRINSIC_BOUNDS: &[u64] = &[0, 10, 50, 100, 500, 1000];
+
+/// Averages of blocks grouped by their number of extrinsics.
+///
+/// Shows how the PoV size and weight scale with the number of transactions. Use the
+/// custom [`fmt::Display`] implementation to print it as a table with a row for every
+/// bucket that contains blocks.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::ExtrinsicBuckets, BlockStats, Bytes};
+///
+/// let mut buckets = ExtrinsicBuckets::new([0, 10]);
+/// for (num_extrinsics, pov_len) in [(0, 100), (5, 200), (10, 400), (11, 1000)] {
+///     buckets.push(&BlockStats { num_extrinsics, pov_len: Bytes(pov_len), ..Default::default() });
+/// }
+/// let buckets: Vec<_> = buckets.buckets().collect();
+/// assert_eq!(buckets[1].min_extrinsics, 1);
+/// assert_eq!(buckets[1].max_extrinsics, Some(10));
+/// assert_eq!(buckets[1].avg_pov_len, Bytes(300));
+/// assert_eq!(buckets[2].max_extrinsics, None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExtrinsicBuckets {
+    /// Sorted upper bounds (inclusive) of all but the last bucket.
+    bounds: Vec<u64>,
+    /// One more than there are bounds as the last bucket is open ended.
+    totals: Vec<BucketTotals>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct BucketTotals {
+    blocks: u64,
+    pov_len: u128,
+    ref_time: u128,
+    proof_size: u128,
+}
+
+impl ExtrinsicBuckets {
+    /// Group blocks into buckets ending at each of the `bounds` (inclusive).
+    ///
+    /// Blocks with more extrinsics than the largest bound go into an additional open
+    /// ended bucket. The bounds are sorted and duplicates are removed.
+    pub fn new(bounds: impl IntoIterator<Item = u64>) -> Self {
+        let mut bounds: Vec<u64> = bounds.into_iter().collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        Self {
+            totals: vec![BucketTotals::default(); bounds.len() + 1],
+            bounds,
+        }
+    }
+
+    /// Add a block to the bucket matching its number of extrinsics.
+    pub fn push(&mut self, stats: &BlockStats) {
+        let index = self
+            .bounds
+            .partition_point(|bound| *bound < stats.num_extrinsics);
+        let totals = &mut self.totals[index];
+        totals.blocks += 1;
+        totals.pov_len += u128::from(stats.pov_len);
+        totals.ref_time += u128::from(stats.weight.ref_time);
+        totals.proof_size += u128::from(stats.weight.proof_size);
+    }
+
+    /// All buckets ordered by the number of extrinsics including empty ones.
+    pub fn buckets(&self) -> impl Iterator<Item = ExtrinsicBucket> + '_ {
+        self.totals.iter().enumerate().map(|(index, totals)| {
+            let blocks = totals.blocks as usize;
+            ExtrinsicBucket {
+                min_extrinsics: index
+                    .checked_sub(1)
+                    .map_or(0, |previous| self.bounds[previous].saturating_add(1)),
+                max_extrinsics: self.bounds.get(index).copied(),
+                blocks: totals.blocks,
+                avg_pov_len: Bytes(average(totals.pov_len, blocks)),
+                avg_weight: Weight::from_parts(
+                    average(totals.ref_time, blocks),
+                    average(totals.proof_size, blocks),
+                ),
+            }
+        })
+    }
+
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
+}
+
+impl Default for ExtrinsicBuckets {
+    /// Buckets ending at [`DEFAULT_EXTRINSIC_BOUNDS`].
+    fn default() -> Self {
+        Self::new(DEFAULT_EXTRINSIC_BOUNDS.iter().copied())
+    }
+}
+
+impl fmt::Display for ExtrinsicBuckets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for ExtrinsicBuckets {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
+        write!(
+            f,
+            "{:>11} {:>6} {:>10} {:>13} {:>13}",
+            "Extrinsics", "Blocks", "PoV Avg", "RefTime Avg", "ProofSize Avg"
+        )?;
+        for bucket in self.buckets().filter(|bucket| bucket.blocks > 0) {
+            let range = match bucket.max_extrinsics {
+                Some(max) if max == bucket.min_extrinsics => max.to_string(),
+                Some(max) => format!("{}-{max}", bucket.min_extrinsics),
+                None => format!("{}+", bucket.min_extrinsics),
+            };
+            write!(
+                f,
+                "\n{:>11} {:>6} {:>10} {:>11}ms {:>13}",
+                range,
+                bucket.blocks,
+                config.size(bucket.avg_pov_len),
+                bucket.avg_weight.ref_time() / 1_000_000_000,
+                config.size(bucket.avg_weight.proof_size()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The averages of the blocks in one bucket of [`ExtrinsicBuckets`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtrinsicBucket {
+    /// The least number of extrinsics of blocks in this bucket.
+    pub min_extrinsics: u64,
+    /// The most number of extrinsics of blocks in this bucket (inclusive).
+    ///
+    /// `None` for the last bucket which is open ended.
+    pub max_extrinsics: Option<u64>,
+    /// Number of blocks in this bucket.
+    pub blocks: u64,
+    /// Average PoV size. Zero if the bucket is empty.
+    pub avg_pov_len: Bytes,
+    /// Average used weight. Zero if the bucket is empty.
+    pub avg_weight: Weight,
+}
+
+/// Buckets of a [`Baseline`] with fewer blocks than this are not compared against.
+///
+/// The 95th percentile of only a handful of blocks says little about the distribution.
+pub const MIN_BASELINE_BLOCKS: u64 = 20;
+
+/// Records a run to create a [`Baseline`] from.
+///
+/// Blocks are grouped by the number of extrinsics they contain in buckets of powers of
+/// two so that later blocks are only compared against blocks with a similar load.
+///
+/// Every block adds three values to keep the percentiles exact, so the memory grows
+/// with the number of blocks recorded. That is about 24 bytes per block, or 24 MB for
+/// a million blocks.
+#[derive(Clone, Debug, Default)]
+pub struct BaselineRecorder {
+    /// Indexed by [`bucket`]. The values are the PoV size, `ref_time` and `proof_size`.
+    buckets: BTreeMap<u32, [SortedValues; 3]>,
+}
+
+impl BaselineRecorder {
+    /// Add a block to the recording.
+    pub fn push(&mut self, stats: &BlockStats) {
+        let values = self
+            .buckets
+            .entry(bucket(stats.num_extrinsics))
+            .or_default();
+        values[0].insert(stats.pov_len.into());
+        values[1].insert(stats.weight.ref_time);
+        values[2].insert(stats.weight.proof_size);
+    }
+
+    /// The distribution of all blocks pushed so far.
+    pub fn finish(&self) -> Baseline {
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|(index, [pov_len, ref_time, proof_size])| {
+                let (min_extrinsics, max_extrinsics) = bucket_range(*index);
+                BaselineBucket {
+                    min_extrinsics,
+                    max_extrinsics,
+                    blocks: pov_len.0.len() as u64,
+                    pov_len_p95: Bytes(pov_len.percentile(95.0)),
+                    ref_time_p95: ref_time.percentile(95.0),
+                    proof_size_p95: proof_size.percentile(95.0),
+                }
+            })
+            .collect();
+        Baseline { buckets }
+    }
+}
+
+/// The distribution of a recorded run that later runs are compared against.
+///
+/// Created by a [`BaselineRecorder`]. Enable the `serde` feature to store it in a file.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::{BaselineRecorder, Metric}, BlockStats, Bytes, Percent};
+///
+/// let mut recorder = BaselineRecorder::default();
+/// for pov_len in 1..=100 {
+///     recorder.push(&BlockStats { pov_len: Bytes(pov_len), num_extrinsics: 5, ..Default::default() });
+/// }
+/// let baseline = recorder.finish();
+///
+/// let block = BlockStats { pov_len: Bytes(120), num_extrinsics: 6, ..Default::default() };
+/// let regressions = baseline.compare(&block, Percent::from_percent(20.0));
+/// assert_eq!(regressions.len(), 1);
+/// assert_eq!(regressions[0].metric, Metric::PovLen);
+/// assert_eq!(regressions[0].baseline, 95);
+///
+/// // Blocks with a different number of extrinsics are compared against other blocks.
+/// let block = BlockStats { pov_len: Bytes(120), num_extrinsics: 50, ..Default::default() };
+/// assert!(baseline.compare(&block, Percent::from_percent(20.0)).is_empty());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Baseline {
+    buckets: Vec<BaselineBucket>,
+}
+
+impl Baseline {
+    /// All buckets ordered by the number of extrinsics.
+    pub fn buckets(&self) -> &[BaselineBucket] {
+        &self.buckets
+    }
+
+    /// The metrics of `stats` that exceed the 95th percentile of blocks with a similar
+    /// number of extrinsics by more than `tolerance`.
+    ///
+    /// Empty if the baseline has fewer than [`MIN_BASELINE_BLOCKS`] of such blocks.
+    /// Metrics that were always zero in the baseline, like `proof_size` on legacy
+    /// runtimes, are never reported.
+    pub fn compare(&self, stats: &BlockStats, tolerance: Percent) -> Vec<Regression> {
+        let Some(bucket) = self
+            .buckets
+            .iter()
+            .find(|bucket| bucket.contains(stats.num_extrinsics))
+            .filter(|bucket| bucket.blocks >= MIN_BASELINE_BLOCKS)
+        else {
+            return Vec::new();
+        };
+        [
+            (
+                Metric::PovLen,
+                stats.pov_len.into(),
+                bucket.pov_len_p95.into(),
+            ),
+            (Metric::RefTime, stats.weight.ref_time, bucket.ref_time_p95),
+            (
+                Metric::ProofSize,
+                stats.weight.proof_size,
+                bucket.proof_size_p95,
+            ),
+        ]
+        .into_iter()
+        .map(|(metric, observed, baseline)| Regression {
+            metric,
+            observed,
+            baseline,
+        })
+        .filter(|regression| regression.baseline > 0 && regression.excess() > tolerance)
+        .collect()
+    }
+}
+
+/// The distribution of the blocks of a [`Baseline`] within a range of extrinsic counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BaselineBucket {
+    /// The least number of extrinsics of blocks in this bucket.
+    pub min_extrinsics: u64,
+    /// The most number of extrinsics of blocks in this bucket (inclusive).
+    pub max_extrinsics: u64,
+    /// How many blocks of the recording fell into this bucket.
+    pub blocks: u64,
+    /// The 95th percentile of the PoV size.
+    pub pov_len_p95: Bytes,
+    /// The 95th percentile of the used `ref_time`.
+    pub ref_time_p95: u64,
+    /// The 95th percentile of the used `proof_size`.
+    pub proof_size_p95: u64,
+}
+
+impl BaselineBucket {
+    /// Whether blocks with `num_extrinsics` belong into this bucket.
+    pub fn contains(&self, num_extrinsics: u64) -> bool {
+        (self.min_extrinsics..=self.max_extrinsics).contains(&num_extrinsics)
+    }
+}
+
+/// A metric compared by [`Baseline::compare`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// [`BlockStats::pov_len`].
+    PovLen,
+    /// The `ref_time` of [`BlockStats::weight`].
+    RefTime,
+    /// The `proof_size` of [`BlockStats::weight`].
+    ProofSize,
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::PovLen => "PoV Size",
+            Self::RefTime => "RefTime",
+            Self::ProofSize => "ProofSize",
+        })
+    }
+}
+
+/// A block metric that is considerably higher than in the [`Baseline`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Regression {
+    /// Which metric regressed.
+    pub metric: Metric,
+    /// The value of the block.
+    pub observed: u64,
+    /// The 95th percentile of the value in the baseline.
+    pub baseline: u64,
+}
+
+impl Regression {
+    /// How much higher the observed value is than the baseline.
+    pub fn excess(&self) -> Percent {
+        Percent::from_ratio(self.observed as f64 / self.baseline as f64 - 1.0)
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} is {} above the baseline P95 of {}",
+            self.metric,
+            self.observed,
+            self.excess(),
+            self.baseline,
+        )
+    }
+}
+
+/// Blocks per row of the windows table of a [`SessionReport`] if not configured.
+pub const DEFAULT_REPORT_WINDOW: usize = 100;
+
+/// Everything worth sharing about a monitoring run, e.g. in an incident ticket.
+///
+/// Combines [`SessionTotals`], consecutive windows of [`WindowStats`] and
+/// [`ExtrinsicBuckets`]. Render it with [`Self::to_markdown`]. It keeps a rendered row
+/// of every filled window, so it grows by one row per `window_size` blocks.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::SessionReport, BlockStats, Bytes};
+/// use std::time::Duration;
+///
+/// let mut report = SessionReport::new("ws://localhost:9944", 2, Duration::from_secs(12));
+/// for number in 1..=3 {
+///     report.push(&BlockStats { number, pov_len: Bytes(1024), max_pov: Bytes(4096), ..Default::default() });
+/// }
+/// let markdown = report.to_markdown();
+/// assert!(markdown.starts_with("# Block stats of ws://localhost:9944"));
+/// assert!(markdown.contains("| #1-#2 |"));
+/// assert!(markdown.contains("| #3-#3 |"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SessionReport {
+    chain: String,
+    /// Number and `spec_version` of the first and the latest block.
+    range: Option<((u32, u32), (u32, u32))>,
+    /// The parachain ID of the latest block that had one.
+    para_id: Option<u32>,
+    totals: SessionTotals,
+    buckets: ExtrinsicBuckets,
+    /// The window that is currently being filled.
+    window: WindowStats,
+    window_size: usize,
+    /// One row for every window that was completely filled.
+    ///
+    /// Grows with the length of the session, by one row per `window_size` blocks.
+    rows: Vec<String>,
+}
+
+impl SessionReport {
+    /// Start a report about the chain described by `chain`, e.g. its name or URL.
+    ///
+    /// Every `window_size` blocks a row is added to the windows table. A `window_size` of
+    /// zero is treated as one. `block_interval` is passed on to [`WindowStats::new`].
+    pub fn new(chain: impl Into<String>, window_size: usize, block_interval: Duration) -> Self {
+        Self {
+            chain: chain.into(),
+            range: None,
+            para_id: None,
+            totals: SessionTotals::default(),
+            buckets: ExtrinsicBuckets::default(),
+            window: WindowStats::new(window_size, block_interval),
+            window_size: window_size.max(1),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Account for a new block.
+    pub fn push(&mut self, stats: &BlockStats) {
+        let block = (stats.number, stats.spec_version);
+        self.range = Some(
+            self.range
+                .map_or((block, block), |(first, _)| (first, block)),
+        );
+        self.para_id = stats.para_id.or(self.para_id);
+        self.totals.push(stats);
+        self.buckets.push(stats);
+        self.window.push(stats.clone());
+        if self.window.len() == self.window_size {
+            self.rows.push(window_row(&self.window));
+            self.window = WindowStats::new(self.window_size, self.window.block_interval);
+        }
+    }
+
+    /// The totals over all blocks of the report.
+    pub fn totals(&self) -> &SessionTotals {
+        &self.totals
+    }
+
+    /// Render the report as markdown.
+    ///
+    /// A header about the chain is followed by a table with a row for every window and
+    /// a table of the blocks grouped by their number of extrinsics. A window that isn't
+    /// full yet gets a row as well. Sizes are shown in the default units of
+    /// [`DisplayConfig`].
+    pub fn to_markdown(&self) -> String {
+        let config = DisplayConfig::default();
+        let totals = &self.totals;
+        let mut markdown = format!("# Block stats of {}\n\n", self.chain);
+        if let Some(((first, first_spec), (last, last_spec))) = self.range {
+            let runtime = if first_spec == last_spec {
+                first_spec.to_string()
+            } else {
+                format!("{first_spec} to {last_spec}")
+            };
+            let para_id = self
+                .para_id
+                .map(|id| format!("| Parachain ID | {id} |\n"))
+                .unwrap_or_default();
+            markdown += &format!(
+                "| | |\n\
+                |---|---|\n\
+                | Blocks | #{first} to #{last} ({} blocks) |\n\
+                | Runtime | spec_version {runtime} |\n\
+                {para_id}\
+                | PoV size | Sum {} Avg {} Max {} |\n\
+                | Idle blocks | {} |\n\
+                | Capacity score | Avg {} |\n\
+                | PoV efficiency | {:.3} |\n\n",
+                totals.blocks(),
+                config.sum(totals.total_pov_len()),
+                config.size(totals.avg_pov_len()),
+                config.size(totals.max_pov_len()),
+                totals.idle_blocks(),
+                totals.avg_capacity_score(),
+                totals.pov_efficiency(),
+            );
+        } else {
+            markdown += "No blocks were received.\n\n";
+        }
+        markdown += &format!(
+            "## Windows of {} blocks\n\n\
+            | Blocks | PoV Avg | PoV Max | PoV % Avg | PoV % Max | RefTime % Avg | ProofSize % Avg |\n\
+            |---|---|---|---|---|---|---|\n",
+            self.window_size,
+        );
+        for row in &self.rows {
+            markdown += row;
+        }
+        if !self.window.is_empty() {
+            markdown += &window_row(&self.window);
+        }
+        markdown += "\n## Blocks by number of extrinsics\n\n\
+            | Extrinsics | Blocks | PoV Avg | RefTime Avg | ProofSize Avg |\n\
+            |---|---|---|---|---|\n";
+        for bucket in self.buckets.buckets().filter(|bucket| bucket.blocks > 0) {
+            let range = match bucket.max_extrinsics {
+                Some(max) if max == bucket.min_extrinsics => max.to_string(),
+                Some(max) => format!("{}-{max}", bucket.min_extrinsics),
+                None => format!("{}+", bucket.min_extrinsics),
+            };
+            markdown += &format!(
+                "| {range} | {} | {} | {}ms | {} |\n",
+                bucket.blocks,
+                config.size(bucket.avg_pov_len),
+                bucket.avg_weight.ref_time() / 1_000_000_000,
+                config.size(bucket.avg_weight.proof_size()),
+            );
+        }
+        markdown
+    }
+}
+
+/// The `ref_time` used by every call over a session in the folded stack format.
+///
+/// Every line has the form `pallet;call weight` where the weight is the summed up
+/// `ref_time` in picoseconds. Tools like `flamegraph.pl` or `inferno-flamegraph` turn it
+/// into a flamegraph showing where the execution time of the chain goes. Needs
+/// [`BlockStats::call_weights`], blocks without them are ignored.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{aggregate::WeightFlamegraph, BlockStats, CallWeight, Weight};
+///
+/// let call = |pallet: &str, call: &str, ref_time| CallWeight {
+///     pallet: pallet.into(),
+///     call: call.into(),
+///     weight: Weight::from_parts(ref_time, 0),
+/// };
+/// let block = BlockStats {
+///     call_weights: Some(vec![
+///         call("Timestamp", "set", 10),
+///         call("Balances", "transfer_keep_alive", 200),
+///         call("Balances", "transfer_keep_alive", 300),
+///     ]),
+///     ..Default::default()
+/// };
+/// let mut flamegraph = WeightFlamegraph::default();
+/// flamegraph.push(&block);
+/// assert_eq!(
+///     flamegraph.to_folded(),
+///     "Balances;transfer_keep_alive 500\nTimestamp;set 10\n",
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WeightFlamegraph {
+    /// Summed up `ref_time` by pallet and call name.
+    ref_time: BTreeMap<(String, String), u128>,
+}
+
+impl WeightFlamegraph {
+    /// Account for the calls of a new block.
+    pub fn push(&mut self, stats: &BlockStats) {
+        for call in stats.call_weights.iter().flatten() {
+            *self
+                .ref_time
+                .entry((call.pallet.clone(), call.call.clone()))
+                .or_default() += u128::from(call.weight.ref_time);
+        }
+    }
+
+    /// The folded lines sorted by pallet and call.
+    pub fn to_folded(&self) -> String {
+        self.ref_time
+            .iter()
+            .map(|((pallet, call), ref_time)| format!("{pallet};{call} {ref_time}\n"))
+            .collect()
+    }
+}
+
+/// A row of the windows table of [`SessionReport::to_markdown`].
+fn window_row(window: &WindowStats) -> String {
+    let config = DisplayConfig::default();
+    let number = |stats: Option<&BlockStats>| stats.map_or(0, |stats| stats.number);
+    format!(
+        "| #{}-#{} | {} | {} | {} | {} | {} | {} |\n",
+        number(window.blocks().next()),
+        number(window.blocks().last()),
+        config.size(window.avg_pov_len()),
+        config.size(window.max_pov_len()),
+        window.avg_pov_percent(),
+        window.max_pov_percent(),
+        window.avg_ref_time_percent(),
+        window.avg_proof_size_percent(),
+    )
+}
+
+/// Index of the baseline bucket of blocks with `num_extrinsics`.
+///
+/// Zero for empty blocks and the bit length of `num_extrinsics` otherwise.
+fn bucket(num_extrinsics: u64) -> u32 {
+    u64::BITS - num_extrinsics.leading_zeros()
+}
+
+/// The smallest and largest number of extrinsics in bucket `index`.
+fn bucket_range(index: u32) -> (u64, u64) {
+    match index {
+        0 => (0, 0),
+        index => (1 << (index - 1), u64::MAX >> (u64::BITS - index)),
+    }
+}
+
+/// A multiset of values kept in ascending order.
+///
+/// Percentiles are looked up directly instead of sorting on every call.
+#[derive(Clone, Debug, Default)]
+struct SortedValues(Vec<u64>);
+
+impl SortedValues {
+    fn insert(&mut self, value: u64) {
+        let index = self.0.partition_point(|other| *other < value);
+        self.0.insert(index, value);
+    }
+
+    fn remove(&mut self, value: u64) {
+        if let Ok(index) = self.0.binary_search(&value) {
+            self.0.remove(index);
+        }
+    }
+
+    /// Nearest rank percentile.
+    fn percentile(&self, p: f64) -> u64 {
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * self.0.len() as f64).ceil() as usize;
+        self.0
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Average of `count` values that summed up to `sum`.
+///
+/// The result always fits into an `u64` because every summand did.
+fn average(sum: u128, count: usize) -> u64 {
+    (sum / count.max(1) as u128) as u64
+}
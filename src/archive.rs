@@ -0,0 +1,322 @@
+//! A [`StatsSource`] built on the `archive_unstable_*` RPC methods.
+//!
+//! Many archive nodes disable the legacy RPC methods. This source only uses the new
+//! `archive` API to compute the stats of historical blocks.
+
+use crate::{
+    source::{
+        block_weight_address, decode_block_weight, decode_block_weight_with, max_block_weights,
+        runtime_state, storage_value_ty, RuntimeMetadata, WeightVersion,
+    },
+    BlockStatsError, BlockStream, BlockWeights, Header, PerDispatchClass, RawBlockStats,
+    RuntimeVersion, StatsSource, StorageLocation, Weight,
+};
+use codec::{Compact, CompactLen, Decode};
+use futures::{future::BoxFuture, FutureExt};
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+use subxt::{
+    backend::{
+        legacy::LegacyRpcMethods,
+        rpc::{rpc_params, RpcClient},
+    },
+    ext::sp_core::{hashing::twox_128, Bytes, H256},
+    storage::StorageAddress,
+    Metadata, PolkadotConfig as DefaultConfig,
+};
+
+/// A [`StatsSource`] that only uses the `archive_unstable_*` RPC methods.
+///
+/// The storage proof size isn't available through the `archive` API. It is only
+/// reported when the node additionally exposes `dev_getBlockStats` and is zero
+/// otherwise. Following new blocks isn't supported by this source. Use it through
+/// [`stats_for_range_with_archive`](crate::stats_for_range_with_archive).
+#[derive(Clone)]
+pub struct ArchiveSource {
+    rpc_client: RpcClient,
+    block_weights: BlockWeights,
+    weight_version: WeightVersion,
+    block_weight_key: Vec<u8>,
+    block_weight_ty: u32,
+    /// The metadata as of the latest finalized block when the source was created.
+    metadata: Metadata,
+    /// The metadata of every runtime encountered so far by `spec_version`.
+    runtime_metadata: RuntimeMetadata,
+    /// The height of the latest finalized block seen so far.
+    finalized: Arc<AtomicU32>,
+}
+
+impl ArchiveSource {
+    /// Create a source from a connected RPC client.
+    ///
+    /// This already fetches the metadata as of the latest finalized block.
+    pub async fn new(rpc_client: RpcClient) -> Result<Self, BlockStatsError> {
+        let finalized = finalized_height(&rpc_client).await?;
+        let hash = hash_by_height(&rpc_client, finalized)
+            .await?
+            .ok_or(BlockStatsError::UnknownBlockNumber(finalized))?;
+        let metadata = metadata(&rpc_client, hash).await?;
+        let (block_weights, weight_version) = max_block_weights(&metadata)?;
+        let spec_version = runtime_version(&rpc_client, hash).await?.spec_version;
+        Ok(Self {
+            block_weight_key: storage_key(&StorageLocation::default(), &metadata)?,
+            block_weight_ty: storage_value_ty(&metadata, &StorageLocation::default())?,
+            rpc_client,
+            block_weights,
+            weight_version,
+            runtime_metadata: RuntimeMetadata::new(spec_version, metadata.clone()),
+            metadata,
+            finalized: Arc::new(AtomicU32::new(finalized)),
+        })
+    }
+
+    /// The hash of the block with the given `number` if it is known.
+    ///
+    /// Only blocks that are finalized are considered. Above the finalized head the node
+    /// would return the blocks of every fork.
+    pub async fn block_hash(&self, number: u32) -> Result<Option<H256>, BlockStatsError> {
+        if number > self.finalized.load(Ordering::Relaxed) {
+            let finalized = finalized_height(&self.rpc_client).await?;
+            self.finalized.fetch_max(finalized, Ordering::Relaxed);
+            if number > finalized {
+                return Ok(None);
+            }
+        }
+        hash_by_height(&self.rpc_client, number).await
+    }
+
+    /// The header of the block with the given `hash`.
+    pub async fn header(&self, hash: H256) -> Result<Header, BlockStatsError> {
+        let header: Option<Bytes> = self
+            .rpc_client
+            .request("archive_unstable_header", rpc_params![hash])
+            .await?;
+        let header = header.ok_or(BlockStatsError::BlockNotAvailable(hash))?;
+        Header::decode(&mut &header[..]).map_err(|err| BlockStatsError::UnexpectedResponse {
+            method: "archive_unstable_header",
+            reason: err.to_string(),
+        })
+    }
+
+    /// The metadata of the runtime `spec_version` which is stored in the state of the
+    /// block with the given `hash`.
+    async fn metadata_at(
+        &self,
+        hash: H256,
+        spec_version: u32,
+    ) -> Result<Metadata, BlockStatsError> {
+        self.runtime_metadata
+            .get(spec_version, || metadata(&self.rpc_client, hash))
+            .await
+    }
+
+    /// The value of the storage item at `key` in the block with the given `hash`.
+    async fn storage(&self, hash: H256, key: &[u8]) -> Result<Option<Vec<u8>>, BlockStatsError> {
+        let items = [serde_json::json!({
+            "key": Bytes(key.to_vec()),
+            "type": "value",
+        })];
+        let response: serde_json::Value = self
+            .rpc_client
+            .request(
+                "archive_unstable_storage",
+                rpc_params![hash, items, Option::<()>::None],
+            )
+            .await?;
+        // Items that don't exist are left out of the result.
+        let value = serde_json::from_value::<Option<Bytes>>(response["result"][0]["value"].clone())
+            .map_err(|err| BlockStatsError::UnexpectedResponse {
+                method: "archive_unstable_storage",
+                reason: err.to_string(),
+            })?;
+        Ok(value.map(|value| value.0))
+    }
+}
+
+impl StatsSource for ArchiveSource {
+    fn block_stats(&self, hash: H256) -> BoxFuture<'_, Result<RawBlockStats, BlockStatsError>> {
+        async move {
+            let body: Option<Vec<Bytes>> = self
+                .rpc_client
+                .request("archive_unstable_body", rpc_params![hash])
+                .await?;
+            let body = body.ok_or(BlockStatsError::BlockNotAvailable(hash))?;
+            let header: Option<Bytes> = self
+                .rpc_client
+                .request("archive_unstable_header", rpc_params![hash])
+                .await?;
+            let header = header.ok_or(BlockStatsError::BlockNotAvailable(hash))?;
+            let num_extrinsics = body.len() as u64;
+            // The encoded block is the header followed by the length prefixed extrinsics.
+            let block_len = header.len()
+                + Compact::<u32>::compact_len(&(body.len() as u32))
+                + body.iter().map(|ext| ext.len()).sum::<usize>();
+            let witness_len = LegacyRpcMethods::<DefaultConfig>::new(self.rpc_client.clone())
+                .dev_get_block_stats(hash)
+                .await
+                .ok()
+                .flatten()
+                .map_or(0, |stats| stats.witness_len);
+            Ok(RawBlockStats {
+                witness_len: crate::Bytes(witness_len),
+                block_len: crate::Bytes(block_len as u64),
+                num_extrinsics,
+            })
+        }
+        .boxed()
+    }
+
+    fn block_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        async move {
+            match self.storage(hash, &self.block_weight_key).await? {
+                Some(value) => decode_block_weight(
+                    self.weight_version,
+                    &value,
+                    self.block_weight_ty,
+                    self.metadata.types(),
+                ),
+                None => Ok(PerDispatchClass::default()),
+            }
+        }
+        .boxed()
+    }
+
+    fn block_weight_at<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'a, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        async move {
+            let Some(value) = self.storage(hash, &self.block_weight_key).await? else {
+                return Ok(PerDispatchClass::default());
+            };
+            let metadata = self
+                .metadata_at(runtime_state(hash, header), version.spec_version)
+                .await?;
+            decode_block_weight_with(&metadata, &StorageLocation::default(), &value)
+        }
+        .boxed()
+    }
+
+    fn runtime_version<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<RuntimeVersion, BlockStatsError>> {
+        runtime_version(&self.rpc_client, runtime_state(hash, header)).boxed()
+    }
+
+    fn max_block_weights(&self) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        futures::future::ready(Ok(self.block_weights)).boxed()
+    }
+
+    fn max_block_weights_at(
+        &self,
+        hash: H256,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        async move {
+            let metadata = self.metadata_at(hash, version.spec_version).await?;
+            Ok(max_block_weights(&metadata)?.0)
+        }
+        .boxed()
+    }
+
+    fn subscribe_blocks(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>> {
+        futures::future::ready(Err(BlockStatsError::Unsupported(
+            "following new blocks with the archive API",
+        )))
+        .boxed()
+    }
+}
+
+/// The height of the latest finalized block.
+async fn finalized_height(rpc_client: &RpcClient) -> Result<u32, BlockStatsError> {
+    Ok(rpc_client
+        .request("archive_unstable_finalizedHeight", rpc_params![])
+        .await?)
+}
+
+/// The hash of the block with the given `number`, which must not be above the finalized head.
+async fn hash_by_height(
+    rpc_client: &RpcClient,
+    number: u32,
+) -> Result<Option<H256>, BlockStatsError> {
+    let hashes: Vec<H256> = rpc_client
+        .request("archive_unstable_hashByHeight", rpc_params![number])
+        .await?;
+    Ok(hashes.into_iter().next())
+}
+
+/// The version of the runtime stored in the state of the block with the given `hash`.
+async fn runtime_version(
+    rpc_client: &RpcClient,
+    hash: H256,
+) -> Result<RuntimeVersion, BlockStatsError> {
+    let version = call(rpc_client, hash, "Core_version").await?;
+    // spec_name, impl_name, authoring_version, spec_version, impl_version, apis,
+    // transaction_version
+    type Encoded = (String, String, u32, u32, u32, Vec<([u8; 8], u32)>, u32);
+    let (_, _, _, spec_version, _, _, transaction_version) = Encoded::decode(&mut &version[..])
+        .map_err(|err| BlockStatsError::UnexpectedResponse {
+            method: "Core_version",
+            reason: err.to_string(),
+        })?;
+    Ok(RuntimeVersion {
+        spec_version,
+        transaction_version,
+    })
+}
+
+/// The metadata of the runtime at the block with the given `hash`.
+async fn metadata(rpc_client: &RpcClient, hash: H256) -> Result<Metadata, BlockStatsError> {
+    let metadata = call(rpc_client, hash, "Metadata_metadata").await?;
+    Vec::<u8>::decode(&mut &metadata[..])
+        .and_then(|metadata| Metadata::decode(&mut &metadata[..]))
+        .map_err(|err| BlockStatsError::UnexpectedResponse {
+            method: "Metadata_metadata",
+            reason: err.to_string(),
+        })
+}
+
+/// Call the runtime API `function` without arguments at the block with the given `hash`.
+async fn call(
+    rpc_client: &RpcClient,
+    hash: H256,
+    function: &'static str,
+) -> Result<Vec<u8>, BlockStatsError> {
+    let response: serde_json::Value = rpc_client
+        .request(
+            "archive_unstable_call",
+            rpc_params![hash, function, Bytes(Vec::new())],
+        )
+        .await?;
+    let unexpected = |reason: String| BlockStatsError::UnexpectedResponse {
+        method: function,
+        reason,
+    };
+    if response["success"] != true {
+        return Err(unexpected(response["error"].to_string()));
+    }
+    let value = serde_json::from_value::<Bytes>(response["value"].clone())
+        .map_err(|err| unexpected(err.to_string()))?;
+    Ok(value.0)
+}
+
+/// The raw key of the storage item at `location`.
+fn storage_key(
+    location: &StorageLocation,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, BlockStatsError> {
+    let address = block_weight_address::<Weight>(location);
+    let mut key = twox_128(location.pallet.as_bytes()).to_vec();
+    key.extend(twox_128(location.item.as_bytes()));
+    address.append_entry_bytes(metadata, &mut key)?;
+    Ok(key)
+}
@@ -0,0 +1,118 @@
+//! Write stats into a columnar Parquet file for offline analysis.
+
+use crate::{micros, BlockStats};
+use arrow_array::{ArrayRef, FixedSizeBinaryArray, RecordBatch, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+use std::{fs::File, path::Path, sync::Arc};
+
+/// Default number of blocks written as one row group.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 1024;
+
+/// Buffers blocks and writes them to a Parquet file in row groups.
+///
+/// The file is only readable after [`ParquetSink::finish`] was called because
+/// that writes the footer.
+pub struct ParquetSink {
+    writer: ArrowWriter<File>,
+    buffer: Vec<BlockStats>,
+    row_group_size: usize,
+}
+
+impl ParquetSink {
+    /// Create (or truncate) the file at `path`.
+    ///
+    /// Every `row_group_size` blocks the buffered blocks are written to the file.
+    pub fn create(path: impl AsRef<Path>, row_group_size: usize) -> Result<Self, ParquetError> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: ArrowWriter::try_new(file, schema(), None)?,
+            buffer: Vec::with_capacity(row_group_size),
+            row_group_size: row_group_size.max(1),
+        })
+    }
+
+    /// Add a block to the file.
+    pub fn push(&mut self, stats: &BlockStats) -> Result<(), ParquetError> {
+        self.buffer.push(stats.clone());
+        if self.buffer.len() >= self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write all buffered blocks as a new row group.
+    pub fn flush(&mut self) -> Result<(), ParquetError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.writer.write(&record_batch(&self.buffer)?)?;
+        self.writer.flush()?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Write the remaining blocks and the file footer.
+    pub fn finish(mut self) -> Result<(), ParquetError> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+fn schema() -> SchemaRef {
+    let hash = || DataType::FixedSizeBinary(32);
+    Arc::new(Schema::new(vec![
+        Field::new("hash", hash(), false),
+        Field::new("number", DataType::UInt32, false),
+        Field::new("state_root", hash(), false),
+        Field::new("extrinsics_root", hash(), false),
+        Field::new("spec_version", DataType::UInt32, false),
+        Field::new("transaction_version", DataType::UInt32, false),
+        Field::new("pov_len", DataType::UInt64, false),
+        Field::new("witness_len", DataType::UInt64, false),
+        Field::new("len", DataType::UInt64, false),
+        Field::new("ref_time", DataType::UInt64, false),
+        Field::new("proof_size", DataType::UInt64, false),
+        Field::new("num_extrinsics", DataType::UInt64, false),
+        Field::new("max_pov", DataType::UInt64, false),
+        Field::new("max_ref_time", DataType::UInt64, false),
+        Field::new("max_proof_size", DataType::UInt64, false),
+        Field::new("fetch_duration_us", DataType::UInt64, false),
+        Field::new("lag_us", DataType::UInt64, false),
+    ]))
+}
+
+fn record_batch(blocks: &[BlockStats]) -> Result<RecordBatch, ParquetError> {
+    let hashes = |f: fn(&BlockStats) -> [u8; 32]| -> Result<ArrayRef, ParquetError> {
+        Ok(Arc::new(FixedSizeBinaryArray::try_from_iter(
+            blocks.iter().map(f),
+        )?))
+    };
+    let u64s = |f: fn(&BlockStats) -> u64| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(blocks.iter().map(f)))
+    };
+    let u32s = |f: fn(&BlockStats) -> u32| -> ArrayRef {
+        Arc::new(UInt32Array::from_iter_values(blocks.iter().map(f)))
+    };
+    let columns = vec![
+        hashes(|stats| stats.hash.0)?,
+        u32s(|stats| stats.number),
+        hashes(|stats| stats.state_root.0)?,
+        hashes(|stats| stats.extrinsics_root.0)?,
+        u32s(|stats| stats.spec_version),
+        u32s(|stats| stats.transaction_version),
+        u64s(|stats| stats.pov_len.into()),
+        u64s(|stats| stats.witness_len.into()),
+        u64s(|stats| stats.len.into()),
+        u64s(|stats| stats.weight.ref_time),
+        u64s(|stats| stats.weight.proof_size),
+        u64s(|stats| stats.num_extrinsics),
+        u64s(|stats| stats.max_pov.into()),
+        u64s(|stats| stats.max_weight.ref_time),
+        u64s(|stats| stats.max_weight.proof_size),
+        u64s(|stats| micros(stats.fetch_duration)),
+        u64s(|stats| micros(stats.lag)),
+    ];
+    Ok(RecordBatch::try_new(schema(), columns)?)
+}
@@ -0,0 +1,105 @@
+//! A compact binary encoding of the stats for passing them to a local process.
+//!
+//! Every block is written as its bincode encoding prefixed by the length of that
+//! encoding as little endian `u32`. Records longer than [`MAX_RECORD_LEN`] are
+//! rejected so that a corrupt length prefix can't make the reader allocate gigabytes.
+//!
+//! ```
+//! use blockstats::{bincode::{BincodeReader, BincodeWriter}, BlockStats};
+//!
+//! let mut writer = BincodeWriter::new(Vec::new());
+//! writer.write(&BlockStats { number: 42, ..Default::default() }).unwrap();
+//! let bytes = writer.into_inner();
+//!
+//! let mut reader = BincodeReader::new(&bytes[..]);
+//! assert_eq!(reader.next().unwrap().unwrap().number, 42);
+//! assert!(reader.next().is_none());
+//!
+//! let corrupt = u32::MAX.to_le_bytes();
+//! assert!(BincodeReader::new(&corrupt[..]).next().unwrap().is_err());
+//! ```
+
+use crate::BlockStats;
+use std::io::{self, Read, Write};
+
+/// The maximum length of a single record in bytes.
+///
+/// Way more than the encoding of any block needs.
+pub const MAX_RECORD_LEN: u32 = 16 * 1024 * 1024;
+
+/// Writes length prefixed records to `W`.
+///
+/// Wrap `W` into a [`std::io::BufWriter`] if it isn't buffered already.
+pub struct BincodeWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> BincodeWriter<W> {
+    /// Write the records to `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Write a single block.
+    pub fn write(&mut self, stats: &BlockStats) -> Result<(), bincode::Error> {
+        let record = bincode::serialize(stats)?;
+        let len = u32::try_from(record.len())
+            .ok()
+            .filter(|len| *len <= MAX_RECORD_LEN)
+            .ok_or(bincode::ErrorKind::SizeLimit)?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(&record)?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Get back the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads the records written by a [`BincodeWriter`] from `R`.
+///
+/// Iterates over the blocks until `R` is exhausted.
+pub struct BincodeReader<R> {
+    inner: R,
+}
+
+impl<R: Read> BincodeReader<R> {
+    /// Read the records from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// Read the next block or `None` if `R` ended in between two records.
+    ///
+    /// Fails without reading the record if it is longer than [`MAX_RECORD_LEN`].
+    pub fn read(&mut self) -> Result<Option<BlockStats>, bincode::Error> {
+        let mut len = [0; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let len = u32::from_le_bytes(len);
+        if len > MAX_RECORD_LEN {
+            return Err(bincode::ErrorKind::SizeLimit.into());
+        }
+        let mut record = vec![0; len as usize];
+        self.inner.read_exact(&mut record)?;
+        Ok(Some(bincode::deserialize(&record)?))
+    }
+}
+
+impl<R: Read> Iterator for BincodeReader<R> {
+    type Item = Result<BlockStats, bincode::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
@@ -0,0 +1,416 @@
+//! Configure a subscription beyond the defaults.
+
+use crate::{
+    subscribe_with_fetcher, BlockStats, BlockStatsError, BlockStatsStream, Percent, StatsFetcher,
+    StorageLocation, SubxtSource,
+};
+use futures::{
+    channel::oneshot::{self, Canceled},
+    TryStreamExt,
+};
+use futures_timer::Delay;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use subxt::{
+    backend::rpc::{RpcClient, RpcClientT},
+    ext::sp_core::H256,
+};
+
+/// Delay before the first reconnection attempt of [`StatsSubscriptionBuilder::wait_for_node`].
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Upper bound for the delay between connection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Configure and start a subscription to the stats of new blocks.
+///
+/// [`subscribe_stats`](crate::subscribe_stats) is a shortcut for using the default
+/// configuration.
+pub struct StatsSubscriptionBuilder {
+    node: Node,
+    block_weight_storage: StorageLocation,
+    validate_metadata: bool,
+    wait_for_node: Option<Duration>,
+    decode_events: bool,
+    decode_author: bool,
+    count_signed: bool,
+    count_messages: bool,
+    verify_block_len: bool,
+    system_overhead_pallets: Vec<String>,
+    relay: Option<Node>,
+    strict_limits: bool,
+    strict_stats: bool,
+    track_finality: bool,
+    soft_pov_budget: Option<f64>,
+    #[cfg(feature = "serde")]
+    capture_raw: Option<std::path::PathBuf>,
+    on_runtime_upgrade: Option<Box<dyn FnMut(RuntimeUpgrade) + Send>>,
+}
+
+enum Node {
+    Url(String),
+    Client(RpcClient),
+}
+
+impl StatsSubscriptionBuilder {
+    /// Connect to the node at `url` which needs to be a websocket.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_node(Node::Url(url.into()))
+    }
+
+    /// Use an already connected client.
+    pub fn with_client(rpc_client: RpcClient) -> Self {
+        Self::with_node(Node::Client(rpc_client))
+    }
+
+    /// Use any implementation of subxt's RPC client interface.
+    ///
+    /// This allows passing a `jsonrpsee` client that was built with custom settings
+    /// like a bigger maximum message size or a different ping interval.
+    pub fn with_rpc_client<R: RpcClientT>(client: R) -> Self {
+        Self::with_client(RpcClient::new(client))
+    }
+
+    fn with_node(node: Node) -> Self {
+        Self {
+            node,
+            block_weight_storage: StorageLocation::default(),
+            validate_metadata: false,
+            wait_for_node: None,
+            decode_events: false,
+            decode_author: false,
+            count_signed: false,
+            count_messages: false,
+            verify_block_len: false,
+            system_overhead_pallets: Vec::new(),
+            relay: None,
+            strict_limits: false,
+            strict_stats: false,
+            track_finality: false,
+            soft_pov_budget: None,
+            #[cfg(feature = "serde")]
+            capture_raw: None,
+            on_runtime_upgrade: None,
+        }
+    }
+
+    /// Read the used weight from a different storage item than `System::BlockWeight`.
+    ///
+    /// See [`SubxtSource::block_weight_storage`].
+    pub fn block_weight_storage(mut self, location: StorageLocation) -> Self {
+        self.block_weight_storage = location;
+        self
+    }
+
+    /// Fail on subscribing if the storage layout of the runtime isn't understood.
+    ///
+    /// See [`SubxtSource::validate_metadata`].
+    pub fn validate_metadata(mut self, validate: bool) -> Self {
+        self.validate_metadata = validate;
+        self
+    }
+
+    /// Decode the events of every block to fill in [`BlockStats::num_fee_paying`](crate::BlockStats::num_fee_paying),
+    /// [`BlockStats::largest_extrinsic_weight`](crate::BlockStats::largest_extrinsic_weight) and
+    /// [`BlockStats::call_weights`](crate::BlockStats::call_weights).
+    ///
+    /// Costs three additional RPC calls per block for the header, the body and the events.
+    /// Enabling [`Self::count_signed`], [`Self::verify_block_len`] or
+    /// [`Self::system_overhead_pallets`] as well adds no further calls. See
+    /// [`SubxtSource::decode_events`].
+    pub fn decode_events(mut self, decode: bool) -> Self {
+        self.decode_events = decode;
+        self
+    }
+
+    /// Find out which authority produced every block to fill in [`BlockStats::author`](crate::BlockStats::author).
+    ///
+    /// See [`SubxtSource::decode_author`].
+    pub fn decode_author(mut self, decode: bool) -> Self {
+        self.decode_author = decode;
+        self
+    }
+
+    /// Count the signed extrinsics of every block to fill in [`BlockStats::num_signed`](crate::BlockStats::num_signed).
+    ///
+    /// Costs two additional RPC calls per block for the header and the body, shared with
+    /// [`Self::decode_events`]. See [`SubxtSource::count_signed`].
+    pub fn count_signed(mut self, count: bool) -> Self {
+        self.count_signed = count;
+        self
+    }
+
+    /// Count the cross-chain messages of every block to fill in [`BlockStats::ump_messages`](crate::BlockStats::ump_messages),
+    /// [`BlockStats::dmp_messages`](crate::BlockStats::dmp_messages) and [`BlockStats::hrmp_messages`](crate::BlockStats::hrmp_messages).
+    ///
+    /// See [`SubxtSource::count_messages`].
+    pub fn count_messages(mut self, count: bool) -> Self {
+        self.count_messages = count;
+        self
+    }
+
+    /// Warn when the block length reported by the node doesn't match the encoded block.
+    ///
+    /// Off by default as it costs two additional RPC calls per block for the header and
+    /// the body, shared with [`Self::decode_events`]. See [`SubxtSource::verify_block_len`].
+    pub fn verify_block_len(mut self, verify: bool) -> Self {
+        self.verify_block_len = verify;
+        self
+    }
+
+    /// Exclude the weight of extrinsics of these pallets from [`BlockStats::user_weight`](crate::BlockStats::user_weight).
+    ///
+    /// For example `ParachainSystem` and `Timestamp` to only see the weight caused by
+    /// users. Costs three additional RPC calls per block for the header, the body and the
+    /// events, shared with [`Self::decode_events`]. See
+    /// [`SubxtSource::system_overhead_pallets`].
+    pub fn system_overhead_pallets<P: Into<String>>(
+        mut self,
+        pallets: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.system_overhead_pallets = pallets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Read [`BlockStats::max_pov`](crate::BlockStats::max_pov) from the relay chain `relay_client` is connected to.
+    ///
+    /// Half of the relay chain's `maxPovSize` is used, like the hardcoded default. It is
+    /// read again together with the weight limits after every runtime upgrade of the
+    /// parachain.
+    pub fn relay_client(mut self, relay_client: RpcClient) -> Self {
+        self.relay = Some(Node::Client(relay_client));
+        self
+    }
+
+    /// Like [`Self::relay_client`] but connects to `url` when subscribing.
+    pub fn relay_url(mut self, url: impl Into<String>) -> Self {
+        self.relay = Some(Node::Url(url.into()));
+        self
+    }
+
+    /// Fail with [`BlockStatsError::PovExceedsMax`] on a block whose PoV is larger than
+    /// [`BlockStats::max_pov`](crate::BlockStats::max_pov).
+    ///
+    /// By default such a block is reported with a percentage above 100%. As a valid block
+    /// can't exceed the limit this points at a misconfigured maximum or a faulty node.
+    pub fn strict_limits(mut self, strict: bool) -> Self {
+        self.strict_limits = strict;
+        self
+    }
+
+    /// Fail with [`BlockStatsError::InconsistentStats`] on a block whose size stats reported
+    /// by the node are obviously wrong.
+    ///
+    /// By default a warning is logged and the stats are used anyway. See
+    /// [`RawBlockStats::inconsistency`](crate::RawBlockStats::inconsistency) for what is
+    /// checked.
+    pub fn strict_stats(mut self, strict: bool) -> Self {
+        self.strict_stats = strict;
+        self
+    }
+
+    /// Fill in [`BlockStats::finality_lag`](crate::BlockStats::finality_lag) for every block.
+    ///
+    /// This requires a second subscription to the finalized blocks on the same
+    /// connection. Blocks which arrive before the first finalized block have no lag.
+    pub fn track_finality(mut self, track: bool) -> Self {
+        self.track_finality = track;
+        self
+    }
+
+    /// Flag blocks whose PoV uses more than `percent` of
+    /// [`BlockStats::max_pov`](crate::BlockStats::max_pov).
+    ///
+    /// Meant for an internal alarm below the hard limit, e.g. at 80%. Flagged blocks are
+    /// still emitted, see [`BlockStats::over_soft_budget`](crate::BlockStats::over_soft_budget).
+    pub fn soft_pov_budget(mut self, percent: f64) -> Self {
+        self.soft_pov_budget = Some(percent);
+        self
+    }
+
+    /// Write the raw inputs of every block to the file at `path`.
+    ///
+    /// The file is created (or truncated) when subscribing. Use
+    /// [`capture::recompute`](crate::capture::recompute) to compute the stats from it
+    /// again, e.g. after upgrading this crate.
+    #[cfg(feature = "serde")]
+    pub fn capture_raw(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.capture_raw = Some(path.into());
+        self
+    }
+
+    /// Call `callback` for the first block built by a newer runtime than all blocks before it.
+    ///
+    /// The upgrade is detected from [`BlockStats::spec_version`](crate::BlockStats::spec_version)
+    /// so no additional storage is read. That is the version of the runtime that built
+    /// the block, so the block that enacted the upgrade with `set_code` is still reported
+    /// with the old version and the callback fires for the block after it. The first
+    /// block of the subscription never counts as an upgrade. The callback runs before
+    /// the block is yielded by the stream.
+    ///
+    /// The subscription follows the best chain. Every `spec_version` is reported once, so
+    /// a reorg between blocks built by the old and new runtime doesn't fire again. The
+    /// reported block might however be replaced by a reorg.
+    pub fn on_runtime_upgrade(
+        mut self,
+        callback: impl FnMut(RuntimeUpgrade) + Send + 'static,
+    ) -> Self {
+        self.on_runtime_upgrade = Some(Box::new(callback));
+        self
+    }
+
+    /// Keep retrying to connect for up to `timeout` if the node isn't reachable yet.
+    ///
+    /// Useful when the node is started at the same time, e.g. with docker compose. The
+    /// delay between attempts doubles up to a few seconds. Only applies when connecting
+    /// via a URL.
+    pub fn wait_for_node(mut self, timeout: Duration) -> Self {
+        self.wait_for_node = Some(timeout);
+        self
+    }
+
+    /// Connect to the node and start listening for new blocks.
+    pub async fn subscribe(mut self) -> Result<BlockStatsStream, BlockStatsError> {
+        let callback = self.on_runtime_upgrade.take();
+        let fetcher = self.fetcher().await?;
+        let stats = subscribe_with_fetcher(fetcher).await?;
+        let Some(mut callback) = callback else {
+            return Ok(stats);
+        };
+        // The highest version seen so far. Blocks of an older runtime only show up on forks.
+        let mut highest: Option<u32> = None;
+        Ok(BlockStatsStream::new(stats.inspect_ok(move |stats| {
+            match highest {
+                Some(previous_spec_version) if stats.spec_version > previous_spec_version => {
+                    callback(RuntimeUpgrade {
+                        hash: stats.hash,
+                        number: stats.number,
+                        previous_spec_version,
+                        spec_version: stats.spec_version,
+                    })
+                }
+                Some(_) => return,
+                None => (),
+            }
+            highest = Some(stats.spec_version);
+        })))
+    }
+
+    /// Connect to the node and compute the stats of a single block with these settings.
+    ///
+    /// Pass `None` as `hash` to get the stats of the current best block. Settings that
+    /// only apply to following blocks, like [`Self::on_runtime_upgrade`] or
+    /// [`Self::track_finality`], are ignored.
+    pub async fn fetch_block(self, hash: Option<H256>) -> Result<BlockStats, BlockStatsError> {
+        self.fetcher().await?.fetch_by_hash(hash).await
+    }
+
+    /// Like [`Self::subscribe`] but additionally returns a signal for when blocks arrive.
+    ///
+    /// This allows a supervisor to distinguish between "still connecting" and "receiving
+    /// blocks", e.g. to mark a service as healthy.
+    pub async fn subscribe_with_ready(self) -> Result<(BlockStatsStream, Ready), BlockStatsError> {
+        let stats = self.subscribe().await?;
+        let (sender, receiver) = oneshot::channel();
+        let mut sender = Some(sender);
+        let stats = stats.inspect_ok(move |_| {
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(());
+            }
+        });
+        Ok((BlockStatsStream::new(stats), Ready(receiver)))
+    }
+
+    /// Connect to the node and configure the fetching of stats accordingly.
+    async fn fetcher(self) -> Result<StatsFetcher, BlockStatsError> {
+        let rpc_client = match self.node {
+            Node::Url(url) => connect(&url, self.wait_for_node.unwrap_or_default()).await?,
+            Node::Client(rpc_client) => rpc_client,
+        };
+        let source = SubxtSource::new(rpc_client)
+            .await?
+            .block_weight_storage(self.block_weight_storage)
+            .decode_events(self.decode_events)
+            .decode_author(self.decode_author)
+            .count_signed(self.count_signed)
+            .count_messages(self.count_messages)
+            .verify_block_len(self.verify_block_len)
+            .system_overhead_pallets(self.system_overhead_pallets);
+        if self.validate_metadata {
+            source.validate_metadata()?;
+        }
+        let mut fetcher = StatsFetcher::new(source)
+            .strict_limits(self.strict_limits)
+            .strict_stats(self.strict_stats)
+            .track_finality(self.track_finality)
+            .soft_pov_budget(self.soft_pov_budget.map(Percent::from_percent));
+        #[cfg(feature = "serde")]
+        if let Some(path) = &self.capture_raw {
+            fetcher = fetcher.capture(crate::capture::CaptureWriter::create(path)?);
+        }
+        match self.relay {
+            Some(Node::Url(url)) => {
+                fetcher = fetcher.relay_client(RpcClient::from_url(&url).await?)
+            }
+            Some(Node::Client(relay_client)) => fetcher = fetcher.relay_client(relay_client),
+            None => (),
+        }
+        Ok(fetcher)
+    }
+}
+
+/// Connect to `url` retrying with backoff until `timeout` elapsed.
+async fn connect(url: &str, timeout: Duration) -> Result<RpcClient, BlockStatsError> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match RpcClient::from_url(url).await {
+            Ok(rpc_client) => return Ok(rpc_client),
+            Err(err) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(err.into());
+                }
+                tracing::debug!("Node at {url} not reachable yet: {err}");
+                Delay::new(backoff.min(remaining)).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// A runtime upgrade reported to [`StatsSubscriptionBuilder::on_runtime_upgrade`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuntimeUpgrade {
+    /// The first block seen that was built by the new runtime.
+    ///
+    /// Its parent enacted the upgrade and `System::LastRuntimeUpgrade` changes in this
+    /// block, when the runtime migrations run.
+    pub hash: H256,
+    /// The number of that block.
+    pub number: u32,
+    /// The highest `spec_version` seen before.
+    pub previous_spec_version: u32,
+    /// The `spec_version` of the new runtime.
+    pub spec_version: u32,
+}
+
+/// Resolves once the first block was processed successfully.
+///
+/// Returned by [`StatsSubscriptionBuilder::subscribe_with_ready`]. The stream needs
+/// to be polled for this to happen. Resolves to an error if the stream is dropped
+/// before any block was processed.
+#[derive(Debug)]
+pub struct Ready(oneshot::Receiver<()>);
+
+impl Future for Ready {
+    type Output = Result<(), Canceled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
@@ -0,0 +1,233 @@
+//! Record the raw inputs of the stats to compute them again later.
+//!
+//! Serialized [`BlockStats`](crate::BlockStats) only contain the fields the crate knew
+//! about when they were captured. A capture instead stores what the node returned for
+//! every block: the header, the `dev_getBlockStats` response, the used weight, the
+//! runtime version and its limits. [`recompute`] runs the same construction as a live
+//! subscription on them, so a capture taken today also yields fields and helpers added
+//! in the future.
+//!
+//! Enable it with [`StatsSubscriptionBuilder::capture_raw`](crate::StatsSubscriptionBuilder::capture_raw).
+//! The file contains one JSON encoded [`RawCapture`] per line.
+//!
+//! Only the inputs listed above are captured. Fields that need decoded events, authors
+//! or message counts are `None` after recomputing, as are the ones that depend on when
+//! the block was received.
+
+use crate::{
+    BlockStatsError, BlockStatsStream, BlockStream, BlockWeights, Bytes, Header, PerDispatchClass,
+    RawBlockStats, RuntimeVersion, StatsFetcher, StatsSource, Weight,
+};
+use futures::{
+    future::{self, BoxFuture},
+    stream, FutureExt, TryStreamExt,
+};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use subxt::ext::sp_core::H256;
+
+/// Everything the stats of a block are computed from.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RawCapture {
+    /// The block hash.
+    pub hash: H256,
+    /// The block header.
+    pub header: Header,
+    /// The response of `dev_getBlockStats`.
+    pub stats: RawBlockStats,
+    /// The weight used by the block for each dispatch class.
+    pub weight: PerDispatchClass<Weight>,
+    /// The runtime that produced the block.
+    pub version: RuntimeVersion,
+    /// The weight limits of that runtime.
+    pub block_weights: BlockWeights,
+    /// The maximum PoV size the block was compared against.
+    pub max_pov: Bytes,
+}
+
+/// Appends captures to a file. All clones write to the same file.
+#[derive(Clone)]
+pub(crate) struct CaptureWriter(Arc<Mutex<LineWriter<File>>>);
+
+impl CaptureWriter {
+    /// Create (or truncate) the file at `path`.
+    pub(crate) fn create(path: impl AsRef<Path>) -> Result<Self, BlockStatsError> {
+        let file = File::create(path).map_err(BlockStatsError::Capture)?;
+        Ok(Self(Arc::new(Mutex::new(LineWriter::new(file)))))
+    }
+
+    /// Write a single block as one line.
+    ///
+    /// Every line is flushed right away so that the capture is complete even if the
+    /// process is killed.
+    pub(crate) fn write(&self, capture: &RawCapture) -> Result<(), BlockStatsError> {
+        let mut line =
+            serde_json::to_vec(capture).map_err(|err| BlockStatsError::Capture(err.into()))?;
+        line.push(b'\n');
+        self.0
+            .lock()
+            .expect("never poisoned")
+            .write_all(&line)
+            .map_err(BlockStatsError::Capture)
+    }
+}
+
+/// Compute the stats of every block in the capture file at `path` again.
+///
+/// The file is read lazily, one block at a time, so captures of any length can be
+/// replayed. The blocks are returned in the order they were captured. Their
+/// [`BlockStats::fetch_duration`](crate::BlockStats::fetch_duration) is zero as nothing
+/// is fetched.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{
+///     capture::{self, RawCapture},
+///     BlockWeights, Bytes, Header, PerDispatchClass, RawBlockStats, RuntimeVersion, Weight,
+/// };
+/// use futures::TryStreamExt;
+///
+/// let header = Header {
+///     parent_hash: Default::default(),
+///     number: 7,
+///     state_root: Default::default(),
+///     extrinsics_root: Default::default(),
+///     digest: Default::default(),
+/// };
+/// let capture = RawCapture {
+///     hash: [1; 32].into(),
+///     header,
+///     stats: RawBlockStats {
+///         witness_len: Bytes(3_000),
+///         block_len: Bytes(1_000),
+///         num_extrinsics: 2,
+///     },
+///     weight: PerDispatchClass {
+///         normal: Weight::from_parts(1_000_000, 0),
+///         ..Default::default()
+///     },
+///     version: RuntimeVersion { spec_version: 9_430, transaction_version: 24 },
+///     block_weights: BlockWeights {
+///         base_block: Weight::default(),
+///         max_block: Weight::from_parts(2_000_000_000_000, 5 * 1024 * 1024),
+///         per_class: Default::default(),
+///     },
+///     max_pov: Bytes(5 * 1024 * 1024),
+/// };
+/// let path = std::env::temp_dir().join("blockstats-capture-doctest.jsonl");
+/// std::fs::write(&path, serde_json::to_string(&capture).unwrap() + "\n").unwrap();
+///
+/// let blocks: Vec<_> =
+///     futures::executor::block_on(capture::recompute(&path).unwrap().try_collect()).unwrap();
+/// assert_eq!(blocks.len(), 1);
+/// assert_eq!(blocks[0].number, 7);
+/// assert_eq!(blocks[0].pov_len, Bytes(4_000));
+/// assert_eq!(blocks[0].spec_version, 9_430);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn recompute(path: impl AsRef<Path>) -> Result<BlockStatsStream, BlockStatsError> {
+    let file = File::open(path).map_err(BlockStatsError::Capture)?;
+    let source = CaptureSource::default();
+    let fetcher = StatsFetcher::new(source.clone());
+    let stats = stream::iter(BufReader::new(file).lines())
+        .map_err(BlockStatsError::Capture)
+        .try_filter(|line| future::ready(!line.is_empty()))
+        .and_then(move |line| {
+            let source = source.clone();
+            let fetcher = fetcher.clone();
+            async move {
+                let capture: RawCapture = serde_json::from_str(&line)
+                    .map_err(|err| BlockStatsError::Capture(err.into()))?;
+                source.replay(capture.clone());
+                let mut stats = fetcher.fetch(capture.hash, &capture.header).await?;
+                stats.max_pov = capture.max_pov;
+                stats.fetch_duration = Duration::ZERO;
+                Ok(stats)
+            }
+        });
+    Ok(BlockStatsStream::new(stats))
+}
+
+/// Answers from the block of a capture file that is currently recomputed instead of a node.
+#[derive(Clone, Default)]
+struct CaptureSource {
+    current: Arc<Mutex<Option<RawCapture>>>,
+}
+
+impl CaptureSource {
+    /// Answer for `capture` from now on.
+    fn replay(&self, capture: RawCapture) {
+        *self.current.lock().expect("never poisoned") = Some(capture);
+    }
+
+    fn get<T>(&self, hash: H256, f: impl FnOnce(&RawCapture) -> T) -> Result<T, BlockStatsError> {
+        self.current
+            .lock()
+            .expect("never poisoned")
+            .as_ref()
+            .filter(|capture| capture.hash == hash)
+            .map(f)
+            .ok_or(BlockStatsError::BlockNotAvailable(hash))
+    }
+
+    /// The limits the current block was compared against.
+    fn block_weights(&self) -> Result<BlockWeights, BlockStatsError> {
+        self.current
+            .lock()
+            .expect("never poisoned")
+            .as_ref()
+            .map(|capture| capture.block_weights)
+            .ok_or(BlockStatsError::Unsupported("limits of an empty capture"))
+    }
+}
+
+impl StatsSource for CaptureSource {
+    fn block_stats(&self, hash: H256) -> BoxFuture<'_, Result<RawBlockStats, BlockStatsError>> {
+        future::ready(self.get(hash, |capture| capture.stats)).boxed()
+    }
+
+    fn block_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        future::ready(self.get(hash, |capture| capture.weight)).boxed()
+    }
+
+    fn runtime_version<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<RuntimeVersion, BlockStatsError>> {
+        let _ = header;
+        future::ready(self.get(hash, |capture| capture.version)).boxed()
+    }
+
+    fn max_block_weights(&self) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        future::ready(self.block_weights()).boxed()
+    }
+
+    /// `hash` is the parent, which isn't captured. The limits stored with the block are
+    /// the ones of its runtime `version` already.
+    fn max_block_weights_at(
+        &self,
+        hash: H256,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        let _ = (hash, version);
+        future::ready(self.block_weights()).boxed()
+    }
+
+    /// The blocks are fed one by one by [`recompute`] instead.
+    fn subscribe_blocks(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>> {
+        future::ready(Err(BlockStatsError::Unsupported(
+            "subscribing to a capture",
+        )))
+        .boxed()
+    }
+}
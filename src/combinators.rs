@@ -0,0 +1,828 @@
+//! Adapters that can be applied to the streams returned by this crate.
+//!
+//! None of them buffers without bound. The adapters which need to keep items take
+//! the bound as an argument:
+//!
+//! - [`pausable`] with [`PausePolicy::Buffer`] drops the oldest item kept while paused.
+//! - [`dedup_by_hash`] forgets the oldest hash and hence passes a repeat older than
+//!   its `window`.
+//! - [`annotate`] releases a held back block without an event and drops the unmatched
+//!   event with the lowest block number.
+//! - [`throttle`] drops the oldest item waiting for its turn.
+//! - [`batched`] emits a batch as soon as it holds `count` items.
+//!
+//! All of them only poll their input when they are polled themselves, except for
+//! [`throttle`]. A slow consumer therefore slows down the input instead of piling up
+//! items. [`throttle`] drains its input while waiting on purpose, so that excess items
+//! are dropped instead of slowing down the input.
+
+use crate::{fraction, BlockStats, BlockStatsError, Bytes, Percent, Weight};
+use futures::{
+    future, stream, stream::MapOk, task::AtomicWaker, Future, Stream, TryStream, TryStreamExt,
+};
+use futures_timer::Delay;
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use subxt::ext::sp_core::H256;
+
+/// What happens to items that arrive while a [`Pausable`] stream is paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// Drop them. After resuming only items arriving from then on are emitted.
+    Skip,
+    /// Keep up to this many items and emit them after resuming.
+    ///
+    /// When more items arrive the oldest ones are dropped. Zero behaves like
+    /// [`Self::Skip`].
+    Buffer(usize),
+}
+
+/// Wrap `stream` so that its emission can be paused and resumed.
+///
+/// The inner stream is still polled while paused so that it doesn't fall behind.
+/// What happens to the items received in the meantime is decided by `policy`.
+pub fn pausable<S: Stream + Unpin>(stream: S, policy: PausePolicy) -> (Pausable<S>, PauseControl) {
+    let shared = Arc::new(Shared::default());
+    let control = PauseControl {
+        shared: shared.clone(),
+    };
+    let pausable = Pausable {
+        inner: Some(stream),
+        policy,
+        buffer: VecDeque::new(),
+        shared,
+    };
+    (pausable, control)
+}
+
+/// Handle to pause and resume a stream created by [`pausable`].
+///
+/// Can be cloned and sent to other tasks.
+#[derive(Clone, Debug)]
+pub struct PauseControl {
+    shared: Arc<Shared>,
+}
+
+impl PauseControl {
+    /// Stop emitting items.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Continue emitting items.
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        self.shared.waker.wake();
+    }
+
+    /// Whether the stream is currently paused.
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    paused: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Stream returned by [`pausable`].
+#[derive(Debug)]
+pub struct Pausable<S: Stream> {
+    /// `None` once the inner stream is exhausted.
+    inner: Option<S>,
+    policy: PausePolicy,
+    buffer: VecDeque<S::Item>,
+    shared: Arc<Shared>,
+}
+
+// Buffered items are never pinned.
+impl<S: Stream + Unpin> Unpin for Pausable<S> {}
+
+impl<S: Stream + Unpin> Stream for Pausable<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.shared.waker.register(cx.waker());
+        loop {
+            let paused = this.shared.paused.load(Ordering::SeqCst);
+            if !paused {
+                if let Some(item) = this.buffer.pop_front() {
+                    return Poll::Ready(Some(item));
+                }
+            }
+            let Some(inner) = this.inner.as_mut() else {
+                // Buffered items are still waiting for `resume`.
+                return if paused && !this.buffer.is_empty() {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(None)
+                };
+            };
+            match Pin::new(inner).poll_next(cx) {
+                Poll::Ready(Some(item)) if paused => {
+                    if let PausePolicy::Buffer(capacity) = this.policy {
+                        if capacity > 0 {
+                            if this.buffer.len() == capacity {
+                                this.buffer.pop_front();
+                            }
+                            this.buffer.push_back(item);
+                        }
+                    }
+                }
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => this.inner = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// What happens to items that arrive faster than a [`Throttle`] stream may emit them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThrottlePolicy {
+    /// Keep up to this many items and emit them one after another.
+    ///
+    /// When more items arrive the oldest ones are dropped. Zero behaves like one.
+    DropOldest(usize),
+    /// Only keep the most recent item so that the next emission is as fresh as
+    /// possible.
+    CoalesceLatest,
+}
+
+/// Emit at most `max_per_sec` items per second.
+///
+/// Protects a slow sink (e.g. a rate limited HTTP API) from a fast chain. An item that
+/// arrives after the previous emission is at least `1 / max_per_sec` seconds old is
+/// emitted right away. Items arriving in between are kept according to `policy`. The
+/// inner stream is drained while waiting so that excess items are dropped instead of
+/// slowing it down. Errors are items like any other and can be dropped as well. A
+/// `max_per_sec` of zero is treated as one.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::combinators::{throttle, ThrottlePolicy};
+/// use futures::{executor::block_on, stream, StreamExt};
+///
+/// let throttled = throttle(stream::iter(1..=5), 100, ThrottlePolicy::CoalesceLatest);
+/// let items: Vec<_> = block_on(throttled.collect());
+/// assert_eq!(items, [1, 5]);
+/// ```
+pub fn throttle<S: Stream + Unpin>(
+    stream: S,
+    max_per_sec: u32,
+    policy: ThrottlePolicy,
+) -> Throttle<S> {
+    Throttle {
+        inner: Some(stream),
+        interval: Duration::from_secs(1) / max_per_sec.max(1),
+        capacity: match policy {
+            ThrottlePolicy::DropOldest(capacity) => capacity.max(1),
+            ThrottlePolicy::CoalesceLatest => 1,
+        },
+        buffer: VecDeque::new(),
+        delay: None,
+    }
+}
+
+/// Stream returned by [`throttle`].
+#[derive(Debug)]
+pub struct Throttle<S: Stream> {
+    /// `None` once the inner stream is exhausted.
+    inner: Option<S>,
+    interval: Duration,
+    capacity: usize,
+    buffer: VecDeque<S::Item>,
+    /// Elapses when the next item may be emitted. `None` if that is right away.
+    delay: Option<Delay>,
+}
+
+// Buffered items are never pinned.
+impl<S: Stream + Unpin> Unpin for Throttle<S> {}
+
+impl<S: Stream + Unpin> Stream for Throttle<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Some(inner) = this.inner.as_mut() {
+            match Pin::new(inner).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if this.buffer.len() == this.capacity {
+                        this.buffer.pop_front();
+                    }
+                    this.buffer.push_back(item);
+                    // Emit right away instead of draining an input that is always ready.
+                    if this.delay.is_none() {
+                        break;
+                    }
+                }
+                Poll::Ready(None) => this.inner = None,
+                Poll::Pending => break,
+            }
+        }
+        if this.buffer.is_empty() && this.inner.is_none() {
+            return Poll::Ready(None);
+        }
+        if let Some(delay) = this.delay.as_mut() {
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.delay = None;
+        }
+        match this.buffer.pop_front() {
+            Some(item) => {
+                this.delay = Some(Delay::new(this.interval));
+                Poll::Ready(Some(item))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Collect items into batches of up to `count` items.
+///
+/// A batch is emitted once it is full or `max_delay` after its first item arrived,
+/// whichever happens first. This reduces the per item overhead of batch oriented
+/// sinks like columnar databases. An error is emitted on its own right after the batch
+/// of the items before it. The last batch may be smaller and is emitted when the
+/// inner stream ends. A `count` of zero is treated as one.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{combinators::batched, BlockStatsError};
+/// use futures::{executor::block_on, stream, TryStreamExt};
+/// use std::time::Duration;
+///
+/// let items = stream::iter((1..=5).map(Ok::<_, BlockStatsError>));
+/// let batches: Vec<_> = block_on(batched(items, 2, Duration::from_secs(1)).try_collect()).unwrap();
+/// assert_eq!(batches, [vec![1, 2], vec![3, 4], vec![5]]);
+/// ```
+pub fn batched<S: TryStream + Unpin>(stream: S, count: usize, max_delay: Duration) -> Batched<S> {
+    Batched {
+        inner: Some(stream),
+        count: count.max(1),
+        max_delay,
+        batch: Vec::new(),
+        delay: None,
+        error: None,
+    }
+}
+
+/// Stream returned by [`batched`].
+#[derive(Debug)]
+pub struct Batched<S: TryStream> {
+    /// `None` once the inner stream is exhausted.
+    inner: Option<S>,
+    count: usize,
+    max_delay: Duration,
+    batch: Vec<S::Ok>,
+    /// Elapses when the current batch is due. `None` while the batch is empty.
+    delay: Option<Delay>,
+    /// An error that is emitted after the batch of the items before it.
+    error: Option<S::Error>,
+}
+
+// Batched items are never pinned.
+impl<S: TryStream + Unpin> Unpin for Batched<S> {}
+
+impl<S: TryStream + Unpin> Batched<S> {
+    /// Take the current batch and stop waiting for it to be due.
+    fn take_batch(&mut self) -> Vec<S::Ok> {
+        self.delay = None;
+        std::mem::take(&mut self.batch)
+    }
+}
+
+impl<S: TryStream + Unpin> Stream for Batched<S> {
+    type Item = Result<Vec<S::Ok>, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if !this.batch.is_empty() && (this.error.is_some() || this.inner.is_none()) {
+                return Poll::Ready(Some(Ok(this.take_batch())));
+            }
+            if let Some(err) = this.error.take() {
+                return Poll::Ready(Some(Err(err)));
+            }
+            let Some(inner) = this.inner.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match Pin::new(inner).try_poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => {
+                    if this.batch.is_empty() {
+                        this.delay = Some(Delay::new(this.max_delay));
+                    }
+                    this.batch.push(item);
+                    if this.batch.len() >= this.count {
+                        return Poll::Ready(Some(Ok(this.take_batch())));
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => this.error = Some(err),
+                Poll::Ready(None) => this.inner = None,
+                Poll::Pending => {
+                    let due = this
+                        .delay
+                        .as_mut()
+                        .is_some_and(|delay| Pin::new(delay).poll(cx).is_ready());
+                    return if due {
+                        Poll::Ready(Some(Ok(this.take_batch())))
+                    } else {
+                        Poll::Pending
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Drop blocks whose hash was already emitted.
+///
+/// Useful when merging multiple subscriptions or after reconnecting which can replay
+/// blocks. Only the `window` most recently emitted hashes are remembered so memory
+/// stays bounded. A repeat older than that is passed through. A `window` of zero is
+/// treated as one. Errors are always passed through.
+pub fn dedup_by_hash<S>(
+    stream: S,
+    window: usize,
+) -> impl TryStream<Ok = BlockStats, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    let mut seen = RecentHashes::new(window);
+    stream.try_filter(move |stats| future::ready(seen.insert(stats.hash)))
+}
+
+/// Fail if the block number drops by more than `max_reorg_depth`.
+///
+/// Every block is compared against the highest block number seen so far. Smaller drops
+/// are accepted as reorgs and the blocks passed through. A larger drop hints at a
+/// misbehaving or forked node and yields [`BlockStatsError::NonMonotonic`] instead of
+/// the block. The offending block doesn't count towards the highest number so that
+/// following blocks are still checked against the last trusted one. Errors of `stream`
+/// are passed through.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{combinators::require_monotonic, BlockStats, BlockStatsError};
+/// use futures::{executor::block_on, stream, StreamExt, TryStreamExt};
+///
+/// let stats = stream::iter([10, 11, 10, 12, 5].map(|number| {
+///     Ok::<_, BlockStatsError>(BlockStats { number, ..Default::default() })
+/// }));
+/// let checked: Vec<_> = block_on(require_monotonic(stats, 2).into_stream().collect());
+/// let numbers: Vec<_> = checked[..4].iter().map(|stats| stats.as_ref().unwrap().number).collect();
+/// assert_eq!(numbers, [10, 11, 10, 12]);
+/// assert!(matches!(checked[4], Err(BlockStatsError::NonMonotonic { highest: 12, number: 5, .. })));
+/// ```
+pub fn require_monotonic<S>(
+    stream: S,
+    max_reorg_depth: u32,
+) -> impl TryStream<Ok = BlockStats, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+    S::Error: From<BlockStatsError>,
+{
+    let mut highest: Option<u32> = None;
+    stream.and_then(move |stats| {
+        let result = match highest {
+            Some(highest) if highest.saturating_sub(stats.number) > max_reorg_depth => {
+                Err(BlockStatsError::NonMonotonic {
+                    highest,
+                    number: stats.number,
+                    max_reorg_depth,
+                }
+                .into())
+            }
+            _ => {
+                highest = highest.max(Some(stats.number));
+                Ok(stats)
+            }
+        };
+        future::ready(result)
+    })
+}
+
+/// Log a warning for every block whose storage proof consists of unusually many small
+/// trie nodes.
+///
+/// Uses the heuristic of [`BlockStats::is_witness_node_heavy`] with `min_avg_node_len`.
+/// Such a block can be expensive for relay chain validators to decode even though its
+/// PoV is well below the size limit, a failure mode that monitoring the size alone
+/// misses. Blocks and errors are passed through unchanged.
+///
+/// None of the sources of this crate count the nodes: `dev_getBlockStats` only reports
+/// the size of the proof and the proof itself isn't exposed over RPC. So this never
+/// warns unless you plug in your own [`StatsSource`](crate::StatsSource) that implements
+/// [`StatsSource::witness_node_count`](crate::StatsSource::witness_node_count), e.g. one
+/// backed by a collator that records its proofs.
+pub fn warn_witness_node_heavy<S>(
+    stream: S,
+    min_avg_node_len: Bytes,
+) -> impl TryStream<Ok = BlockStats, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    stream.inspect_ok(move |stats| {
+        let (Some(avg), Some(nodes)) = (stats.avg_witness_node_len(), stats.witness_nodes) else {
+            return;
+        };
+        if avg < min_avg_node_len {
+            tracing::warn!(
+                "Block #{} has {nodes} witness nodes of {avg} on average. Its PoV may be expensive to decode.",
+                stats.number,
+            );
+        }
+    })
+}
+
+/// Convert every block into a type of your own.
+///
+/// Same as [`TryStreamExt::map_ok`] but constrained to streams of [`BlockStats`] so that
+/// the closure's argument type doesn't need to be annotated. The returned type can be
+/// named and is `Unpin` and `Send` whenever `stream` and `f` are.
+pub fn map_stats<S, F, T>(stream: S, f: F) -> MapOk<S, F>
+where
+    S: TryStream<Ok = BlockStats>,
+    F: FnMut(BlockStats) -> T,
+{
+    stream.map_ok(f)
+}
+
+/// Pair every block with `label`.
+///
+/// Useful to tell apart the blocks of multiple subscriptions (e.g. one per node)
+/// after merging them into a single stream.
+pub fn labeled<S>(
+    stream: S,
+    label: impl Into<String>,
+) -> impl TryStream<Ok = (String, BlockStats), Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    let label = label.into();
+    stream.map_ok(move |stats| (label.clone(), stats))
+}
+
+/// The weight a block used in one dispatch class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassUsage {
+    /// The weight used by extrinsics of the class.
+    pub weight: Weight,
+    /// The most weight extrinsics of the class may use.
+    ///
+    /// The class' `max_total` or the block's `max_weight` if the class has no limit of
+    /// its own.
+    pub limit: Weight,
+}
+
+impl ClassUsage {
+    /// Used weight as a percentage of [`Self::limit`].
+    ///
+    /// The more exhausted of both weight components is used.
+    pub fn fill_percent(&self) -> Percent {
+        Percent::of_weight(self.weight, self.limit)
+    }
+}
+
+/// The weight usage of a block split by dispatch class.
+///
+/// Returned by [`by_class`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassBreakdown {
+    /// The block hash.
+    pub hash: H256,
+    /// The block number.
+    pub number: u32,
+    /// Usage by `Normal` extrinsics.
+    pub normal: ClassUsage,
+    /// Usage by `Operational` extrinsics.
+    pub operational: ClassUsage,
+    /// Usage by `Mandatory` extrinsics.
+    pub mandatory: ClassUsage,
+}
+
+impl From<&BlockStats> for ClassBreakdown {
+    fn from(stats: &BlockStats) -> Self {
+        let usage = |weight: Weight, max_total: Option<Weight>| ClassUsage {
+            weight,
+            limit: max_total.unwrap_or(stats.max_weight),
+        };
+        let (weight, limits) = (stats.class_weight, stats.class_limits);
+        Self {
+            hash: stats.hash,
+            number: stats.number,
+            normal: usage(weight.normal, limits.normal.max_total),
+            operational: usage(weight.operational, limits.operational.max_total),
+            mandatory: usage(weight.mandatory, limits.mandatory.max_total),
+        }
+    }
+}
+
+/// Split the weight usage of every block by dispatch class.
+///
+/// Useful to chart the classes separately from a single subscription. Errors are passed
+/// through.
+pub fn by_class<S>(stream: S) -> impl TryStream<Ok = ClassBreakdown, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    stream.map_ok(|stats| ClassBreakdown::from(&stats))
+}
+
+/// Attach events of your own to the blocks with the same number.
+///
+/// `events` yields the block number each event belongs to. As the event for a block
+/// might arrive after the block, up to `buffer` blocks are held back to wait for it.
+/// Blocks are released right away once `events` ended. At most `buffer` events (but at
+/// least one) that didn't match a block yet are kept, the ones with the lowest block
+/// number are dropped first. `events` is drained whenever the returned stream is
+/// polled. A later event for the same block replaces an earlier one. Errors are
+/// passed through immediately.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{combinators::annotate, BlockStats, BlockStatsError};
+/// use futures::{executor::block_on, stream, TryStreamExt};
+///
+/// let stats = stream::iter((1..=3).map(|number| {
+///     Ok::<_, BlockStatsError>(BlockStats { number, ..Default::default() })
+/// }));
+/// let events = stream::iter([(2, "deployment")]);
+/// let annotated: Vec<_> = block_on(annotate(stats, events, 4).try_collect()).unwrap();
+/// let events: Vec<_> = annotated.iter().map(|(stats, event)| (stats.number, *event)).collect();
+/// assert_eq!(events, [(1, None), (2, Some("deployment")), (3, None)]);
+/// ```
+pub fn annotate<S, E, T>(stats: S, events: E, buffer: usize) -> Annotate<S, E, T>
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+    E: Stream<Item = (u32, T)> + Unpin,
+{
+    Annotate {
+        stats: Some(stats),
+        events: Some(events),
+        buffer,
+        held: VecDeque::new(),
+        pending: BTreeMap::new(),
+    }
+}
+
+/// Stream returned by [`annotate`].
+#[derive(Debug)]
+pub struct Annotate<S, E, T> {
+    /// `None` once the stream is exhausted.
+    stats: Option<S>,
+    /// `None` once the stream is exhausted.
+    events: Option<E>,
+    buffer: usize,
+    /// Blocks waiting for their event.
+    held: VecDeque<BlockStats>,
+    /// Events waiting for their block.
+    pending: BTreeMap<u32, T>,
+}
+
+// Neither held blocks nor pending events are ever pinned.
+impl<S: Unpin, E: Unpin, T> Unpin for Annotate<S, E, T> {}
+
+impl<S, E, T> Stream for Annotate<S, E, T>
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+    E: Stream<Item = (u32, T)> + Unpin,
+{
+    type Item = Result<(BlockStats, Option<T>), S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Some(events) = this.events.as_mut() {
+            match Pin::new(events).poll_next(cx) {
+                Poll::Ready(Some((number, event))) => {
+                    this.pending.insert(number, event);
+                    if this.pending.len() > this.buffer.max(1) {
+                        this.pending.pop_first();
+                    }
+                }
+                Poll::Ready(None) => this.events = None,
+                Poll::Pending => break,
+            }
+        }
+        loop {
+            let release =
+                this.held.len() > this.buffer || this.events.is_none() || this.stats.is_none();
+            if let Some(stats) = release.then(|| this.held.pop_front()).flatten() {
+                let event = this.pending.remove(&stats.number);
+                return Poll::Ready(Some(Ok((stats, event))));
+            }
+            let Some(stats) = this.stats.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match Pin::new(stats).try_poll_next(cx) {
+                Poll::Ready(Some(Ok(stats))) => this.held.push_back(stats),
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => this.stats = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The change in fill level between a block and its predecessor.
+///
+/// The percentage values are in percentage points. Positive values mean the block is
+/// fuller than the one before.
+#[derive(Clone, Debug)]
+pub struct BlockDelta {
+    /// The block the deltas are computed for.
+    pub stats: BlockStats,
+    /// Change of [`BlockStats::pov_percent`].
+    pub d_pov_percent: f64,
+    /// Change of [`BlockStats::ref_time_percent`].
+    pub d_ref_time_percent: f64,
+    /// Change of [`BlockStats::proof_size_percent`].
+    pub d_proof_size_percent: f64,
+    /// [`BlockStats::pov_len`] divided by the one of the predecessor.
+    ///
+    /// Values much greater than 1.0 flag a sudden spike. `None` if the predecessor's PoV
+    /// was empty.
+    pub pov_growth_ratio: Option<f64>,
+}
+
+/// Compute how the fill level changes from block to block.
+///
+/// A sustained positive delta hints at a chain heading towards saturation. The first
+/// block has no predecessor and is therefore not emitted. Errors are passed through
+/// and don't reset the predecessor.
+pub fn deltas<S>(stream: S) -> impl TryStream<Ok = BlockDelta, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    let mut prev: Option<([f64; 3], Bytes)> = None;
+    stream.try_filter_map(move |stats| {
+        let current = fill_levels(&stats);
+        let delta = prev
+            .replace((current, stats.pov_len))
+            .map(|(prev, prev_pov_len)| BlockDelta {
+                d_pov_percent: current[0] - prev[0],
+                d_ref_time_percent: current[1] - prev[1],
+                d_proof_size_percent: current[2] - prev[2],
+                pov_growth_ratio: (prev_pov_len != Bytes(0))
+                    .then(|| fraction(stats.pov_len, prev_pov_len)),
+                stats,
+            });
+        future::ready(Ok(delta))
+    })
+}
+
+/// Only pass blocks whose fill level differs noticeably from the last one passed.
+///
+/// A block is passed if its PoV size, `ref_time` or `proof_size` percentage moved by more
+/// than `delta` percentage points compared to the last block that was passed. The first
+/// block is always passed. Useful to compress long idle periods before writing to a
+/// database. Errors are always passed through.
+pub fn change_only<S>(
+    stream: S,
+    delta: f64,
+) -> impl TryStream<Ok = BlockStats, Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    let mut last: Option<[f64; 3]> = None;
+    stream.try_filter(move |stats| {
+        let current = fill_levels(stats);
+        let changed = last.map_or(true, |last| {
+            last.iter()
+                .zip(current)
+                .any(|(last, current)| (current - last).abs() > delta)
+        });
+        if changed {
+            last = Some(current);
+        }
+        future::ready(changed)
+    })
+}
+
+/// A limited resource of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    /// [`BlockStats::pov_percent`].
+    PovSize,
+    /// [`BlockStats::ref_time_percent`].
+    RefTime,
+    /// [`BlockStats::proof_size_percent`].
+    ProofSize,
+}
+
+impl Dimension {
+    /// In the same order as [`fill_levels`].
+    const ALL: [Self; 3] = [Self::PovSize, Self::RefTime, Self::ProofSize];
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::PovSize => "PoV Size",
+            Self::RefTime => "RefTime",
+            Self::ProofSize => "ProofSize",
+        })
+    }
+}
+
+/// Only pass blocks that are fuller than every block before them.
+///
+/// A block is emitted together with the [`Dimension`] whenever its fill level in that
+/// dimension is higher than that of all earlier blocks. A block setting a record in
+/// multiple dimensions is emitted once for each of them. Empty dimensions never set a
+/// record. Useful to spot the worst block of a long run. Errors are always passed
+/// through.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{combinators::{records, Dimension}, BlockStats, BlockStatsError, Bytes};
+/// use futures::{executor::block_on, stream, TryStreamExt};
+///
+/// let stats = stream::iter([10, 30, 20, 40].map(|pov_len| {
+///     Ok::<_, BlockStatsError>(BlockStats { pov_len: Bytes(pov_len), max_pov: Bytes(100), ..Default::default() })
+/// }));
+/// let records: Vec<_> = block_on(records(stats).try_collect()).unwrap();
+/// let records: Vec<_> = records.iter().map(|(dimension, stats)| (*dimension, stats.pov_len)).collect();
+/// assert_eq!(records, [(Dimension::PovSize, Bytes(10)), (Dimension::PovSize, Bytes(30)), (Dimension::PovSize, Bytes(40))]);
+/// ```
+pub fn records<S>(
+    stream: S,
+) -> impl TryStream<Ok = (Dimension, BlockStats), Error = S::Error> + Unpin
+where
+    S: TryStream<Ok = BlockStats> + Unpin,
+{
+    let mut max = [0.0; 3];
+    stream
+        .map_ok(move |stats| {
+            let records: Vec<_> = Dimension::ALL
+                .into_iter()
+                .zip(fill_levels(&stats))
+                .zip(&mut max)
+                .filter(|((_, current), max)| current > max)
+                .map(|((dimension, current), max)| {
+                    *max = current;
+                    Ok((dimension, stats.clone()))
+                })
+                .collect();
+            stream::iter(records)
+        })
+        .try_flatten()
+}
+
+/// The percentages of all limited resources in a fixed order.
+fn fill_levels(stats: &BlockStats) -> [f64; 3] {
+    [
+        stats.pov_percent().as_percent(),
+        stats.ref_time_percent().as_percent(),
+        stats.proof_size_percent().as_percent(),
+    ]
+}
+
+/// A set which only keeps the most recently inserted hashes.
+struct RecentHashes {
+    capacity: usize,
+    order: VecDeque<H256>,
+    set: HashSet<H256>,
+}
+
+impl RecentHashes {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            order: VecDeque::new(),
+            set: HashSet::new(),
+        }
+    }
+
+    /// Returns `false` if the hash was already present.
+    fn insert(&mut self, hash: H256) -> bool {
+        if !self.set.insert(hash) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        self.order.push_back(hash);
+        true
+    }
+}
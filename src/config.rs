@@ -0,0 +1,129 @@
+//! Settings of a subscription that can be stored in a file.
+
+use crate::{StatsSubscriptionBuilder, StorageLocation, DEFAULT_URL};
+use std::time::Duration;
+
+/// The settings of a [`StatsSubscriptionBuilder`] as plain data.
+///
+/// Every field corresponds to the builder method of the same name. With the `config`
+/// feature it can be read from a TOML file using kebab case keys. All keys are
+/// optional and unknown keys are rejected:
+///
+/// ```toml
+/// url = "wss://rpc.example.com:443"
+/// relay-url = "wss://relay.example.com:443"
+/// wait-for-node = 30
+/// decode-events = true
+/// system-overhead-pallets = ["ParachainSystem", "Timestamp"]
+///
+/// [block-weight-storage]
+/// pallet = "System"
+/// item = "BlockWeight"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields, rename_all = "kebab-case")
+)]
+pub struct Config {
+    /// The node to connect to. [`DEFAULT_URL`] if not set.
+    pub url: Option<String>,
+    /// See [`StatsSubscriptionBuilder::relay_url`].
+    pub relay_url: Option<String>,
+    /// See [`StatsSubscriptionBuilder::wait_for_node`]. In seconds.
+    pub wait_for_node: Option<u64>,
+    /// See [`StatsSubscriptionBuilder::block_weight_storage`].
+    pub block_weight_storage: Option<StorageLocation>,
+    /// See [`StatsSubscriptionBuilder::validate_metadata`].
+    pub validate_metadata: bool,
+    /// See [`StatsSubscriptionBuilder::decode_events`].
+    pub decode_events: bool,
+    /// See [`StatsSubscriptionBuilder::decode_author`].
+    pub decode_author: bool,
+    /// See [`StatsSubscriptionBuilder::count_signed`].
+    pub count_signed: bool,
+    /// See [`StatsSubscriptionBuilder::count_messages`].
+    pub count_messages: bool,
+    /// See [`StatsSubscriptionBuilder::verify_block_len`].
+    pub verify_block_len: bool,
+    /// See [`StatsSubscriptionBuilder::system_overhead_pallets`].
+    pub system_overhead_pallets: Vec<String>,
+    /// See [`StatsSubscriptionBuilder::strict_limits`].
+    pub strict_limits: bool,
+    /// See [`StatsSubscriptionBuilder::strict_stats`].
+    pub strict_stats: bool,
+    /// See [`StatsSubscriptionBuilder::track_finality`].
+    pub track_finality: bool,
+    /// See [`StatsSubscriptionBuilder::soft_pov_budget`]. In percent.
+    ///
+    /// Being a float this is why `Config` only implements [`PartialEq`].
+    pub soft_pov_budget: Option<f64>,
+}
+
+impl Config {
+    /// Parse the contents of a TOML config file.
+    ///
+    /// ```
+    /// use blockstats::Config;
+    ///
+    /// let config = Config::from_toml("url = \"ws://localhost:9944\"\ndecode-events = true").unwrap();
+    /// assert_eq!(config.url.as_deref(), Some("ws://localhost:9944"));
+    /// assert!(config.decode_events);
+    /// assert!(Config::from_toml("no-such-key = 1").is_err());
+    /// ```
+    #[cfg(feature = "config")]
+    pub fn from_toml(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    /// Read and parse the TOML config file at `path`.
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let toml = std::fs::read_to_string(path)?;
+        Ok(Self::from_toml(&toml)?)
+    }
+
+    /// A builder configured with these settings.
+    ///
+    /// Settings that aren't stored in a config file, like callbacks, can be added to the
+    /// returned builder.
+    pub fn builder(&self) -> StatsSubscriptionBuilder {
+        let mut builder = StatsSubscriptionBuilder::new(self.url.as_deref().unwrap_or(DEFAULT_URL))
+            .validate_metadata(self.validate_metadata)
+            .decode_events(self.decode_events)
+            .decode_author(self.decode_author)
+            .count_signed(self.count_signed)
+            .count_messages(self.count_messages)
+            .verify_block_len(self.verify_block_len)
+            .system_overhead_pallets(&self.system_overhead_pallets)
+            .strict_limits(self.strict_limits)
+            .strict_stats(self.strict_stats)
+            .track_finality(self.track_finality);
+        if let Some(relay_url) = &self.relay_url {
+            builder = builder.relay_url(relay_url);
+        }
+        if let Some(secs) = self.wait_for_node {
+            builder = builder.wait_for_node(Duration::from_secs(secs));
+        }
+        if let Some(location) = &self.block_weight_storage {
+            builder = builder.block_weight_storage(location.clone());
+        }
+        if let Some(percent) = self.soft_pov_budget {
+            builder = builder.soft_pov_budget(percent);
+        }
+        builder
+    }
+}
+
+/// Reading a config file with [`Config::load`] failed.
+#[cfg(feature = "config")]
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    #[error("Failed to read config file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file isn't valid TOML or contains unknown keys.
+    #[error("Invalid config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
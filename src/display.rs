@@ -0,0 +1,196 @@
+//! Control how the stats are pretty printed.
+
+use crate::Percent;
+use std::fmt;
+use subxt::ext::sp_core::H256;
+
+/// The unit sizes in bytes are displayed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeUnit {
+    /// Plain bytes.
+    Bytes,
+    /// Multiples of 1024 bytes.
+    #[default]
+    KiB,
+    /// Multiples of 1024 KiB.
+    MiB,
+    /// Multiples of 1024 MiB.
+    GiB,
+}
+
+impl SizeUnit {
+    /// `bytes` in this unit, rounded down.
+    pub fn scale(self, bytes: impl Into<u64>) -> u64 {
+        bytes.into() / self.bytes()
+    }
+
+    /// The suffix appended to sizes in this unit.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Bytes => "B",
+            Self::KiB => "KiB",
+            Self::MiB => "MiB",
+            Self::GiB => "GiB",
+        }
+    }
+
+    /// The next larger unit. Used for sums which grow with the number of blocks.
+    fn larger(self) -> Self {
+        match self {
+            Self::Bytes => Self::KiB,
+            Self::KiB => Self::MiB,
+            Self::MiB | Self::GiB => Self::GiB,
+        }
+    }
+
+    fn bytes(self) -> u64 {
+        match self {
+            Self::Bytes => 1,
+            Self::KiB => 1024,
+            Self::MiB => 1024 * 1024,
+            Self::GiB => 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// How block hashes are displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashFormat {
+    /// Don't show hashes at all.
+    #[default]
+    Hidden,
+    /// Only the first and last bytes like `0x1234…cdef`.
+    Abbreviated,
+    /// All 32 bytes in hex.
+    Full,
+}
+
+/// Controls units and precision of the numbers displayed by
+/// [`BlockStats::fmt_with`](crate::BlockStats::fmt_with) and friends.
+///
+/// The default matches the [`fmt::Display`] implementations.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::{BlockStats, DisplayConfig, HashFormat, SizeUnit};
+///
+/// let config = DisplayConfig {
+///     size_unit: SizeUnit::Bytes,
+///     percent_decimals: 2,
+///     hash: HashFormat::Abbreviated,
+/// };
+/// let stats = BlockStats::default();
+/// assert_eq!(stats.fmt_with(&DisplayConfig::default()).to_string(), stats.to_string());
+/// assert!(stats.fmt_with(&config).to_string().starts_with("0000 0x0000…0000: PoV Size=0000B(000.00%)"));
+/// ```
+///
+/// The defaults print the same lines as before this configuration existed:
+///
+/// ```
+/// use blockstats::{aggregate::WindowStats, BlockStats, Bytes, Weight};
+/// use std::time::Duration;
+///
+/// let stats = BlockStats {
+///     number: 42,
+///     pov_len: Bytes(3072),
+///     witness_len: Bytes(2048),
+///     len: Bytes(1024),
+///     max_pov: Bytes(4608),
+///     weight: Weight::from_parts(500_000_000_000, 5120),
+///     max_weight: Weight::from_parts(2_000_000_000_000, 10240),
+///     num_extrinsics: 3,
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     stats.to_string(),
+///     "0042: PoV Size=0003KiB(066%) Weight RefTime=0000500ms(025%) Weight ProofSize=0005KiB(050%) \
+///      Witness=0002KiB(067%) Block=0001KiB NumExtrinsics=0003 Capacity=067",
+/// );
+///
+/// let mut window = WindowStats::new(10, Duration::from_secs(12));
+/// window.push(stats);
+/// assert!(window.to_string().ends_with("ProofBound=100.0%"));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DisplayConfig {
+    /// The unit of sizes. Sums over many blocks are shown in the next larger unit.
+    pub size_unit: SizeUnit,
+    /// Number of decimal places of percentages.
+    ///
+    /// Percentages of a limit are rounded down so that a block which is almost full is
+    /// never shown as completely full. Shares that aren't fill levels, like the witness
+    /// share of the PoV and the capacity score, are rounded to the nearest value. The
+    /// proof bound fraction of a window always shows at least one decimal.
+    pub percent_decimals: usize,
+    /// Whether and how to show block hashes.
+    pub hash: HashFormat,
+}
+
+impl DisplayConfig {
+    /// `bytes` zero padded in the configured unit including the suffix.
+    pub(crate) fn size(&self, bytes: impl Into<u64>) -> String {
+        format!(
+            "{:04}{}",
+            self.size_unit.scale(bytes.into()),
+            self.size_unit.suffix()
+        )
+    }
+
+    /// Like [`DisplayConfig::size`] but for sums over many blocks.
+    pub(crate) fn sum(&self, bytes: u128) -> String {
+        let unit = self.size_unit.larger();
+        format!("{:04}{}", bytes / u128::from(unit.bytes()), unit.suffix())
+    }
+
+    /// `percent` zero padded with the configured number of decimals and without a `%`.
+    pub(crate) fn percent(&self, percent: Percent) -> String {
+        let decimals = self.percent_decimals;
+        let factor = 10f64.powi(decimals.min(i32::MAX as usize) as i32);
+        // The epsilon keeps values like 0.29 * 100 = 28.999… from being rounded down.
+        let value = ((percent.as_percent() * factor) + 1e-9).floor() / factor;
+        let width = if decimals == 0 { 3 } else { 4 + decimals };
+        format!("{value:0width$.decimals$}")
+    }
+
+    /// Like [`DisplayConfig::percent`] but rounded to the nearest value.
+    pub(crate) fn rounded_percent(&self, percent: Percent) -> String {
+        let decimals = self.percent_decimals;
+        let width = if decimals == 0 { 3 } else { 4 + decimals };
+        format!("{:0width$.decimals$}", percent.as_percent())
+    }
+
+    /// `percent` rounded with at least one decimal, unpadded and without a `%`.
+    pub(crate) fn fine_percent(&self, percent: Percent) -> String {
+        let decimals = self.percent_decimals.max(1);
+        format!("{:.decimals$}", percent.as_percent())
+    }
+
+    /// The hash preceded by a space or nothing if hashes are hidden.
+    pub(crate) fn hash(&self, hash: H256) -> String {
+        match self.hash {
+            HashFormat::Hidden => String::new(),
+            HashFormat::Abbreviated => format!(" {hash}"),
+            HashFormat::Full => format!(" {hash:?}"),
+        }
+    }
+}
+
+/// Displays a value according to a [`DisplayConfig`].
+///
+/// Returned by the `fmt_with` methods.
+pub struct DisplayWith<'a, T: ?Sized> {
+    pub(crate) value: &'a T,
+    pub(crate) config: &'a DisplayConfig,
+}
+
+/// Types that can be displayed according to a [`DisplayConfig`].
+pub(crate) trait FmtWith {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result;
+}
+
+impl<T: FmtWith + ?Sized> fmt::Display for DisplayWith<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.write_with(f, self.config)
+    }
+}
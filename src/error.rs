@@ -0,0 +1,120 @@
+//! The error type returned by this crate.
+
+use crate::Bytes;
+use subxt::{
+    error::MetadataError,
+    ext::{scale_decode, sp_core::H256},
+};
+
+/// Everything that can go wrong while collecting block statistics.
+#[derive(Debug, thiserror::Error)]
+pub enum BlockStatsError {
+    /// Communicating with the node failed.
+    ///
+    /// Boxed because it is much larger than all the other variants.
+    #[error(transparent)]
+    Subxt(Box<subxt::Error>),
+    /// The metadata returned by the node lacks an item we depend on.
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+    /// The weight limits constant could not be SCALE decoded.
+    ///
+    /// This usually means that the runtime uses a different layout for
+    /// `BlockWeights` than the one this crate was written against.
+    #[error("Failed to decode {pallet}::{constant}: {source}")]
+    WeightsDecode {
+        /// The pallet containing the constant.
+        pallet: &'static str,
+        /// The name of the constant.
+        constant: &'static str,
+        /// The underlying decode error.
+        ///
+        /// Boxed for the same reason as [`Self::Subxt`]. This used to be a
+        /// `codec::Error` before the weights were decoded with the types of the metadata.
+        #[source]
+        source: Box<scale_decode::Error>,
+    },
+    /// The used weight of a block could not be SCALE decoded.
+    ///
+    /// Like [`Self::WeightsDecode`] this used to hold a `codec::Error`.
+    #[error("Failed to decode the used weight of a block: {0}")]
+    BlockWeightDecode(#[source] Box<scale_decode::Error>),
+    /// A storage item we read from doesn't exist or has an unexpected layout.
+    ///
+    /// Only detected upfront when the metadata is validated.
+    #[error("Storage item {pallet}::{item} can't be used: {reason}")]
+    IncompatibleStorage {
+        /// The pallet containing the item.
+        pallet: String,
+        /// The name of the storage item.
+        item: String,
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// The source can't provide the requested data.
+    #[error("Not supported by this source: {0}")]
+    Unsupported(&'static str),
+    /// The node replied with something we don't understand.
+    #[error("Unexpected response to {method}: {reason}")]
+    UnexpectedResponse {
+        /// The RPC method that was called.
+        method: &'static str,
+        /// What is wrong with the response.
+        reason: String,
+    },
+    /// A block's PoV is larger than the maximum allowed PoV size.
+    ///
+    /// Either the maximum is misconfigured or the node misreports the PoV size. Only
+    /// returned if enabled by [`crate::StatsSubscriptionBuilder::strict_limits`].
+    #[error(
+        "PoV of {} bytes exceeds the maximum of {} bytes.",
+        observed.as_u64(),
+        max.as_u64()
+    )]
+    PovExceedsMax {
+        /// The PoV size of the block.
+        observed: Bytes,
+        /// The maximum PoV size.
+        max: Bytes,
+    },
+    /// The node reported size stats for a block that can't be right.
+    ///
+    /// Only returned if enabled by [`crate::StatsSubscriptionBuilder::strict_stats`].
+    /// Otherwise a warning is logged and the stats are used as they are.
+    #[error("Inconsistent stats for block {hash:?}: {reason}")]
+    InconsistentStats {
+        /// The block the stats were reported for.
+        hash: H256,
+        /// What is wrong with them.
+        reason: String,
+    },
+    /// The node doesn't have the requested block (anymore).
+    #[error("Block {0:?} not available.")]
+    BlockNotAvailable(H256),
+    /// The node doesn't know a block with this number.
+    #[error("No block with number {0} known.")]
+    UnknownBlockNumber(u32),
+    /// Reading or writing a capture of the raw inputs failed.
+    ///
+    /// See the [`capture`](crate::capture) module.
+    #[error("Failed to access the capture file: {0}")]
+    Capture(std::io::Error),
+    /// The block number dropped further than a reorg can explain.
+    ///
+    /// Only returned by [`crate::combinators::require_monotonic`].
+    #[error("Block number dropped from {highest} to {number} which exceeds the maximum reorg depth of {max_reorg_depth}.")]
+    NonMonotonic {
+        /// The highest block number seen before.
+        highest: u32,
+        /// The number of the offending block.
+        number: u32,
+        /// The largest drop that is accepted as a reorg.
+        max_reorg_depth: u32,
+    },
+}
+
+impl From<subxt::Error> for BlockStatsError {
+    fn from(err: subxt::Error) -> Self {
+        Self::Subxt(Box::new(err))
+    }
+}
@@ -0,0 +1,139 @@
+//! A fixed-size binary record of the stats for ring buffers and other zero-parse capture.
+//!
+//! Every record is [`FIXED_RECORD_LEN`] bytes and laid out like a `repr(C)` struct
+//! without any padding between the fields. All integers are little endian:
+//!
+//! | Offset | Type       | Field                                   |
+//! |--------|------------|-----------------------------------------|
+//! | 0      | `[u8; 32]` | `hash`                                  |
+//! | 32     | `[u8; 32]` | `state_root`                            |
+//! | 64     | `[u8; 32]` | `extrinsics_root`                       |
+//! | 96     | `u64`      | `pov_len`                               |
+//! | 104    | `u64`      | `witness_len`                           |
+//! | 112    | `u64`      | `len`                                   |
+//! | 120    | `u64`      | `weight.ref_time`                       |
+//! | 128    | `u64`      | `weight.proof_size`                     |
+//! | 136    | `u64`      | `num_extrinsics`                        |
+//! | 144    | `u64`      | `max_pov`                               |
+//! | 152    | `u64`      | `max_weight.ref_time`                   |
+//! | 160    | `u64`      | `max_weight.proof_size`                 |
+//! | 168    | `u64`      | `fetch_duration` in microseconds        |
+//! | 176    | `u64`      | `lag` in microseconds                   |
+//! | 184    | `u32`      | `number`                                |
+//! | 188    | `u32`      | `spec_version`                          |
+//! | 192    | `u32`      | `transaction_version`                   |
+//! | 196    | `u32`      | Always zero, pads to a multiple of 8    |
+//!
+//! Only these fields are contained. All other fields are left at their default when
+//! reading a record back.
+//!
+//! ```
+//! use blockstats::{fixed::FIXED_RECORD_LEN, BlockStats, Bytes};
+//!
+//! let stats = BlockStats { number: 42, pov_len: Bytes(1024), ..Default::default() };
+//! let record: [u8; FIXED_RECORD_LEN] = stats.to_fixed_bytes();
+//! let decoded = BlockStats::from_fixed_bytes(&record);
+//! assert_eq!(decoded.number, 42);
+//! assert_eq!(decoded.pov_len, Bytes(1024));
+//! ```
+
+use crate::{micros, BlockStats, Bytes, Weight};
+use std::time::Duration;
+use subxt::ext::sp_core::H256;
+
+/// The size of a record created by [`BlockStats::to_fixed_bytes`] in bytes.
+pub const FIXED_RECORD_LEN: usize = 200;
+
+impl BlockStats {
+    /// Encode the most important fields into a fixed-size record.
+    ///
+    /// See the [`fixed`](crate::fixed) module for the layout. Durations that don't
+    /// fit into a `u64` of microseconds are saturated.
+    pub fn to_fixed_bytes(&self) -> [u8; FIXED_RECORD_LEN] {
+        let mut record = [0; FIXED_RECORD_LEN];
+        let mut writer = Writer(&mut record);
+        writer.put(self.hash.as_bytes());
+        writer.put(self.state_root.as_bytes());
+        writer.put(self.extrinsics_root.as_bytes());
+        for value in [
+            self.pov_len.into(),
+            self.witness_len.into(),
+            self.len.into(),
+            self.weight.ref_time,
+            self.weight.proof_size,
+            self.num_extrinsics,
+            self.max_pov.into(),
+            self.max_weight.ref_time,
+            self.max_weight.proof_size,
+            micros(self.fetch_duration),
+            micros(self.lag),
+        ] {
+            writer.put(&value.to_le_bytes());
+        }
+        for value in [self.number, self.spec_version, self.transaction_version] {
+            writer.put(&value.to_le_bytes());
+        }
+        record
+    }
+
+    /// Decode a record created by [`Self::to_fixed_bytes`].
+    ///
+    /// Fields that aren't part of the record are left at their default.
+    pub fn from_fixed_bytes(record: &[u8; FIXED_RECORD_LEN]) -> Self {
+        let mut reader = Reader(record);
+        let hash = H256(reader.take());
+        let state_root = H256(reader.take());
+        let extrinsics_root = H256(reader.take());
+        let mut next_u64 = || u64::from_le_bytes(reader.take());
+        let pov_len = Bytes(next_u64());
+        let witness_len = Bytes(next_u64());
+        let len = Bytes(next_u64());
+        let weight = Weight::from_parts(next_u64(), next_u64());
+        let num_extrinsics = next_u64();
+        let max_pov = Bytes(next_u64());
+        let max_weight = Weight::from_parts(next_u64(), next_u64());
+        let fetch_duration = Duration::from_micros(next_u64());
+        let lag = Duration::from_micros(next_u64());
+        let mut next_u32 = || u32::from_le_bytes(reader.take());
+        Self {
+            hash,
+            state_root,
+            extrinsics_root,
+            pov_len,
+            witness_len,
+            len,
+            weight,
+            num_extrinsics,
+            max_pov,
+            max_weight,
+            fetch_duration,
+            lag,
+            number: next_u32(),
+            spec_version: next_u32(),
+            transaction_version: next_u32(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Appends to a byte slice.
+struct Writer<'a>(&'a mut [u8]);
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let (head, tail) = std::mem::take(&mut self.0).split_at_mut(bytes.len());
+        head.copy_from_slice(bytes);
+        self.0 = tail;
+    }
+}
+
+/// Consumes a byte slice from the front.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        head.try_into().expect("split off exactly N bytes")
+    }
+}
@@ -0,0 +1,329 @@
+//! Expose a stats stream to remote consumers over gRPC.
+//!
+//! The protocol is defined in `proto/blockstats.proto` which can be used to
+//! generate clients in any language supported by protobuf.
+
+use crate::{
+    micros, BlockStats, BlockStatsError, CallWeight, PerDispatchClass, Weight, WeightsPerClass,
+};
+use futures::{Stream, StreamExt, TryStream, TryStreamExt};
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+use tokio::sync::broadcast;
+use tonic::{Request, Response, Status};
+
+/// How many blocks a slow client can lag behind before it starts missing blocks.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// The protobuf messages and generated service stubs.
+pub mod proto {
+    /// Mirrors [`crate::Weight`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Weight {
+        #[prost(uint64, tag = "1")]
+        pub ref_time: u64,
+        #[prost(uint64, tag = "2")]
+        pub proof_size: u64,
+    }
+
+    /// Mirrors [`crate::PerDispatchClass`] of [`crate::Weight`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClassWeight {
+        #[prost(message, optional, tag = "1")]
+        pub normal: Option<Weight>,
+        #[prost(message, optional, tag = "2")]
+        pub operational: Option<Weight>,
+        #[prost(message, optional, tag = "3")]
+        pub mandatory: Option<Weight>,
+    }
+
+    /// Mirrors [`crate::CallWeight`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CallWeight {
+        #[prost(string, tag = "1")]
+        pub pallet: String,
+        #[prost(string, tag = "2")]
+        pub call: String,
+        #[prost(message, optional, tag = "3")]
+        pub weight: Option<Weight>,
+    }
+
+    /// Mirrors [`crate::WeightsPerClass`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClassLimit {
+        #[prost(message, optional, tag = "1")]
+        pub base_extrinsic: Option<Weight>,
+        #[prost(message, optional, tag = "2")]
+        pub max_extrinsic: Option<Weight>,
+        #[prost(message, optional, tag = "3")]
+        pub max_total: Option<Weight>,
+        #[prost(message, optional, tag = "4")]
+        pub reserved: Option<Weight>,
+    }
+
+    /// Mirrors [`crate::PerDispatchClass`] of [`crate::WeightsPerClass`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClassLimits {
+        #[prost(message, optional, tag = "1")]
+        pub normal: Option<ClassLimit>,
+        #[prost(message, optional, tag = "2")]
+        pub operational: Option<ClassLimit>,
+        #[prost(message, optional, tag = "3")]
+        pub mandatory: Option<ClassLimit>,
+    }
+
+    /// Mirrors [`crate::BlockStats`].
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BlockStats {
+        #[prost(bytes = "vec", tag = "1")]
+        pub hash: Vec<u8>,
+        #[prost(uint32, tag = "2")]
+        pub number: u32,
+        #[prost(uint64, tag = "3")]
+        pub pov_len: u64,
+        #[prost(uint64, tag = "4")]
+        pub witness_len: u64,
+        #[prost(uint64, tag = "5")]
+        pub len: u64,
+        #[prost(message, optional, tag = "6")]
+        pub weight: Option<Weight>,
+        #[prost(uint64, tag = "7")]
+        pub num_extrinsics: u64,
+        #[prost(uint64, tag = "8")]
+        pub max_pov: u64,
+        #[prost(message, optional, tag = "9")]
+        pub max_weight: Option<Weight>,
+        #[prost(bytes = "vec", tag = "10")]
+        pub state_root: Vec<u8>,
+        #[prost(bytes = "vec", tag = "11")]
+        pub extrinsics_root: Vec<u8>,
+        #[prost(uint32, tag = "12")]
+        pub spec_version: u32,
+        #[prost(uint32, tag = "13")]
+        pub transaction_version: u32,
+        #[prost(message, optional, tag = "14")]
+        pub class_weight: Option<ClassWeight>,
+        #[prost(message, optional, tag = "15")]
+        pub class_limits: Option<ClassLimits>,
+        #[prost(uint64, optional, tag = "16")]
+        pub num_fee_paying: Option<u64>,
+        #[prost(uint64, optional, tag = "17")]
+        pub num_signed: Option<u64>,
+        /// The 32 byte account of the block author.
+        #[prost(bytes = "vec", optional, tag = "18")]
+        pub author: Option<Vec<u8>>,
+        #[prost(uint32, optional, tag = "19")]
+        pub para_id: Option<u32>,
+        #[prost(uint64, optional, tag = "20")]
+        pub ump_messages: Option<u64>,
+        #[prost(uint64, optional, tag = "21")]
+        pub dmp_messages: Option<u64>,
+        #[prost(uint64, optional, tag = "22")]
+        pub hrmp_messages: Option<u64>,
+        #[prost(uint32, optional, tag = "23")]
+        pub finality_lag: Option<u32>,
+        /// Milliseconds since the Unix epoch.
+        #[prost(uint64, optional, tag = "24")]
+        pub received_at: Option<u64>,
+        #[prost(uint64, optional, tag = "25")]
+        pub witness_nodes: Option<u64>,
+        #[prost(message, optional, tag = "26")]
+        pub user_weight: Option<Weight>,
+        #[prost(message, optional, tag = "27")]
+        pub largest_extrinsic_weight: Option<ClassWeight>,
+        /// Empty if the calls aren't decoded.
+        #[prost(message, repeated, tag = "28")]
+        pub call_weights: Vec<CallWeight>,
+        #[prost(message, optional, tag = "29")]
+        pub base_block: Option<Weight>,
+        /// In microseconds, saturated at the maximum value.
+        #[prost(uint64, tag = "30")]
+        pub fetch_duration: u64,
+        /// In microseconds, saturated at the maximum value.
+        #[prost(uint64, tag = "31")]
+        pub lag: u64,
+        #[prost(bool, optional, tag = "32")]
+        pub over_soft_budget: Option<bool>,
+    }
+
+    /// Request to receive all blocks from now on.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {}
+
+    include!(concat!(env!("OUT_DIR"), "/blockstats.BlockStatsService.rs"));
+}
+
+impl From<Weight> for proto::Weight {
+    fn from(weight: Weight) -> Self {
+        Self {
+            ref_time: weight.ref_time,
+            proof_size: weight.proof_size,
+        }
+    }
+}
+
+impl From<PerDispatchClass<Weight>> for proto::ClassWeight {
+    fn from(weight: PerDispatchClass<Weight>) -> Self {
+        Self {
+            normal: Some(weight.normal.into()),
+            operational: Some(weight.operational.into()),
+            mandatory: Some(weight.mandatory.into()),
+        }
+    }
+}
+
+impl From<&CallWeight> for proto::CallWeight {
+    fn from(call: &CallWeight) -> Self {
+        Self {
+            pallet: call.pallet.clone(),
+            call: call.call.clone(),
+            weight: Some(call.weight.into()),
+        }
+    }
+}
+
+impl From<WeightsPerClass> for proto::ClassLimit {
+    fn from(limit: WeightsPerClass) -> Self {
+        Self {
+            base_extrinsic: Some(limit.base_extrinsic.into()),
+            max_extrinsic: limit.max_extrinsic.map(Into::into),
+            max_total: limit.max_total.map(Into::into),
+            reserved: limit.reserved.map(Into::into),
+        }
+    }
+}
+
+impl From<PerDispatchClass<WeightsPerClass>> for proto::ClassLimits {
+    fn from(limits: PerDispatchClass<WeightsPerClass>) -> Self {
+        Self {
+            normal: Some(limits.normal.into()),
+            operational: Some(limits.operational.into()),
+            mandatory: Some(limits.mandatory.into()),
+        }
+    }
+}
+
+impl From<&BlockStats> for proto::BlockStats {
+    fn from(stats: &BlockStats) -> Self {
+        Self {
+            hash: stats.hash.as_bytes().to_vec(),
+            number: stats.number,
+            pov_len: stats.pov_len.into(),
+            witness_len: stats.witness_len.into(),
+            len: stats.len.into(),
+            weight: Some(stats.weight.into()),
+            num_extrinsics: stats.num_extrinsics,
+            max_pov: stats.max_pov.into(),
+            max_weight: Some(stats.max_weight.into()),
+            state_root: stats.state_root.as_bytes().to_vec(),
+            extrinsics_root: stats.extrinsics_root.as_bytes().to_vec(),
+            spec_version: stats.spec_version,
+            transaction_version: stats.transaction_version,
+            class_weight: Some(stats.class_weight.into()),
+            class_limits: Some(stats.class_limits.into()),
+            num_fee_paying: stats.num_fee_paying,
+            num_signed: stats.num_signed,
+            author: stats.author.as_ref().map(|author| author.0.to_vec()),
+            para_id: stats.para_id,
+            ump_messages: stats.ump_messages,
+            dmp_messages: stats.dmp_messages,
+            hrmp_messages: stats.hrmp_messages,
+            finality_lag: stats.finality_lag,
+            received_at: stats.received_at.and_then(|at| {
+                let millis = at.duration_since(UNIX_EPOCH).ok()?.as_millis();
+                u64::try_from(millis).ok()
+            }),
+            witness_nodes: stats.witness_nodes,
+            user_weight: stats.user_weight.map(Into::into),
+            largest_extrinsic_weight: stats.largest_extrinsic_weight.map(Into::into),
+            call_weights: stats
+                .call_weights
+                .iter()
+                .flatten()
+                .map(Into::into)
+                .collect(),
+            base_block: Some(stats.base_block.into()),
+            fetch_duration: micros(stats.fetch_duration),
+            lag: micros(stats.lag),
+            over_soft_budget: stats.over_soft_budget,
+        }
+    }
+}
+
+/// Serve the blocks of `stats` to every client that calls `Subscribe`.
+///
+/// The stream is consumed once and fanned out to all connected clients. A
+/// client only receives the blocks that arrive after it subscribed. Errors
+/// of the stream are forwarded to the clients as `INTERNAL` status.
+///
+/// Runs until the server fails or `stats` ends. In the latter case the streams of all
+/// clients end as well. Must be called from within a tokio runtime.
+pub async fn serve_grpc<S>(stats: S, addr: SocketAddr) -> Result<(), tonic::transport::Error>
+where
+    S: TryStream<Ok = BlockStats, Error = BlockStatsError> + Send + Unpin + 'static,
+{
+    let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+    let sender = Arc::new(Mutex::new(Some(sender)));
+    let service = BlockStatsService {
+        sender: sender.clone(),
+    };
+    let forward = tokio::spawn(async move {
+        let mut stats = stats.into_stream();
+        while let Some(stat) = stats.next().await {
+            if let Some(sender) = &*sender.lock().expect("never poisoned") {
+                // Sending only fails when no client is connected which is fine.
+                let _ = sender.send(stat.as_ref().map(Into::into).map_err(|err| err.to_string()));
+            }
+        }
+        // Dropping the only sender ends the streams of all clients.
+        sender.lock().expect("never poisoned").take();
+    });
+    tonic::transport::Server::builder()
+        .add_service(proto::block_stats_service_server::BlockStatsServiceServer::new(service))
+        .serve_with_shutdown(addr, async {
+            let _ = forward.await;
+        })
+        .await
+}
+
+type Item = Result<proto::BlockStats, String>;
+
+struct BlockStatsService {
+    /// `None` once the stats ended.
+    sender: Arc<Mutex<Option<broadcast::Sender<Item>>>>,
+}
+
+#[tonic::async_trait]
+impl proto::block_stats_service_server::BlockStatsService for BlockStatsService {
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::BlockStats, Status>> + Send>>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let Some(receiver) = self
+            .sender
+            .lock()
+            .expect("never poisoned")
+            .as_ref()
+            .map(broadcast::Sender::subscribe)
+        else {
+            return Err(Status::unavailable("the stats ended"));
+        };
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(item) => return Some((item.map_err(Status::internal), receiver)),
+                    // We rather skip blocks than slowing down everyone else.
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
@@ -0,0 +1,105 @@
+//! An HTTP endpoint for liveness and readiness probes.
+//!
+//! `GET /healthz` answers `200 OK` while blocks keep arriving and
+//! `503 Service Unavailable` once no block arrived for longer than the
+//! configured staleness window.
+
+use crate::{http::read_request_head, BlockStats, BlockStatsError};
+use futures::{TryStream, TryStreamExt};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+};
+
+/// Tracks when the last block arrived.
+///
+/// Cheap to clone. All clones share the same timestamp so that one clone can be
+/// handed to [`serve_health`] while another one records the blocks.
+#[derive(Clone, Debug)]
+pub struct Liveness {
+    last_block: Arc<Mutex<Instant>>,
+    staleness: Duration,
+}
+
+impl Liveness {
+    /// Consider the subscription stalled when no block arrived for `staleness`.
+    ///
+    /// Counts as alive for `staleness` after creation so that a freshly started
+    /// process isn't reported as stalled while it connects.
+    pub fn new(staleness: Duration) -> Self {
+        Self {
+            last_block: Arc::new(Mutex::new(Instant::now())),
+            staleness,
+        }
+    }
+
+    /// Record that a block just arrived.
+    pub fn block_arrived(&self) {
+        *self.last_block.lock().expect("never poisoned") = Instant::now();
+    }
+
+    /// Record every block of `stats` as it passes through.
+    pub fn track<S>(&self, stats: S) -> impl TryStream<Ok = BlockStats, Error = BlockStatsError>
+    where
+        S: TryStream<Ok = BlockStats, Error = BlockStatsError>,
+    {
+        let liveness = self.clone();
+        stats.inspect_ok(move |_| liveness.block_arrived())
+    }
+
+    /// How long ago the last block arrived.
+    pub fn since_last_block(&self) -> Duration {
+        self.last_block.lock().expect("never poisoned").elapsed()
+    }
+
+    /// Whether a block arrived within the staleness window.
+    pub fn is_alive(&self) -> bool {
+        self.since_last_block() <= self.staleness
+    }
+}
+
+/// Answer health probes on `addr` according to `liveness`.
+///
+/// Every path other than `/healthz` gets a `404 Not Found`.
+///
+/// Runs until accepting connections fails. Must be called from within a tokio runtime.
+pub async fn serve_health(liveness: Liveness, addr: SocketAddr) -> Result<(), io::Error> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        tokio::spawn(serve_client(socket, liveness.clone()));
+    }
+}
+
+async fn serve_client(mut socket: TcpStream, liveness: Liveness) {
+    let Some(request) = read_request_head(&mut socket).await else {
+        return;
+    };
+    let path = request
+        .split(|byte| *byte == b' ')
+        .nth(1)
+        .unwrap_or_default();
+    let (status, body) = match path {
+        b"/healthz" if liveness.is_alive() => ("200 OK", "ok"),
+        b"/healthz" => ("503 Service Unavailable", "stalled"),
+        _ => ("404 Not Found", "not found"),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: text/plain\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {body}\n",
+        body.len() + 1,
+    );
+    // The probe will simply retry if this fails.
+    let _ = socket.write_all(response.as_bytes()).await;
+}
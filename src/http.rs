@@ -0,0 +1,24 @@
+//! The bits of HTTP shared by the endpoints the crate serves.
+
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// Requests with a larger head are rejected.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// Read an HTTP request up to the end of its headers.
+///
+/// `None` if the connection was closed or the head is larger than [`MAX_REQUEST_LEN`].
+pub(crate) async fn read_request_head(socket: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(len) => request.extend_from_slice(&buf[..len]),
+        }
+        if request.len() > MAX_REQUEST_LEN {
+            return None;
+        }
+    }
+    Some(request)
+}
@@ -0,0 +1,64 @@
+//! Produce the stats to a Kafka topic.
+//!
+//! Every block is sent as a JSON object keyed by its block number.
+
+use crate::BlockStats;
+use rdkafka::{
+    error::KafkaError,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+    ClientConfig,
+};
+
+/// Errors of the [`KafkaSink`].
+#[derive(Debug, thiserror::Error)]
+pub enum KafkaSinkError {
+    /// The block could not be serialized to JSON.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// Creating the producer or delivering a message failed.
+    #[error(transparent)]
+    Kafka(#[from] KafkaError),
+}
+
+/// Sends blocks to a Kafka topic.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaSink {
+    /// Produce to `topic` of the cluster reachable via `brokers`.
+    ///
+    /// `brokers` is a comma separated list of `host:port` pairs.
+    pub fn new(brokers: &str, topic: impl Into<String>) -> Result<Self, KafkaSinkError> {
+        Self::with_config(ClientConfig::new().set("bootstrap.servers", brokers), topic)
+    }
+
+    /// Like [`Self::new`] but with full control over the producer configuration.
+    pub fn with_config(
+        config: &ClientConfig,
+        topic: impl Into<String>,
+    ) -> Result<Self, KafkaSinkError> {
+        Ok(Self {
+            producer: config.create()?,
+            topic: topic.into(),
+        })
+    }
+
+    /// Send a block and wait until the broker acknowledged it.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn send(&self, stats: &BlockStats) -> Result<(), KafkaSinkError> {
+        let payload = serde_json::to_vec(stats)?;
+        let key = stats.number.to_string();
+        self.producer
+            .send(
+                FutureRecord::to(&self.topic).key(&key).payload(&payload),
+                Timeout::Never,
+            )
+            .await
+            .map_err(|(err, _)| err)?;
+        Ok(())
+    }
+}
@@ -3,144 +3,1660 @@
 //! pool fullness. This is useful to gain insights where about bottlenecks
 //! (computationb vs bandwith).
 
+pub mod aggregate;
+mod archive;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "bincode")]
+pub mod bincode;
+mod builder;
+#[cfg(feature = "serde")]
+pub mod capture;
+pub mod combinators;
+mod config;
+mod display;
+mod error;
+pub mod fixed;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "health")]
+pub mod health;
+#[cfg(any(feature = "health", feature = "sse"))]
+mod http;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "plot")]
+pub mod plot;
+mod relay;
+mod source;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "sse")]
+pub mod sse;
+mod txpool;
+mod weight_stats;
+
+pub use archive::ArchiveSource;
+pub use builder::{Ready, RuntimeUpgrade, StatsSubscriptionBuilder};
+pub use config::Config;
+#[cfg(feature = "config")]
+pub use config::ConfigError;
+pub use display::{DisplayConfig, DisplayWith, HashFormat, SizeUnit};
+pub use error::BlockStatsError;
+pub use relay::{fetch_relay_config, RelayConfig};
+pub use source::{
+    BlockStream, CallWeight, ExtrinsicStats, Header, MessageCounts, RawBlockStats, RuntimeVersion,
+    StatsSource, StorageLocation, SubxtSource,
+};
+pub use txpool::{tx_pool_stream, tx_pool_stream_with_client, TxPoolStats};
+pub use weight_stats::{
+    subscribe_weight_stats, subscribe_weight_stats_with_client, WeightStats, WeightStatsStream,
+};
+
 use core::ops::Add;
-use futures::{TryStream, TryStreamExt};
-use std::{boxed::Box, fmt};
+use display::FmtWith;
+use futures::{
+    future,
+    stream::{self, BoxStream, FusedStream},
+    Stream, StreamExt, TryStreamExt,
+};
+use futures_timer::Delay;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
 use subxt::{
     backend::{legacy::LegacyRpcMethods, rpc::RpcClient},
-    error::MetadataError,
+    config::Header as _,
     ext::{scale_decode, sp_core::H256},
-    storage::{address::StaticStorageMapKey, address::Yes, Address},
-    Error, OnlineClient, PolkadotConfig as DefaultConfig,
+    utils::AccountId32,
+    PolkadotConfig,
 };
 
+/// Environment variable consulted by [`subscribe_stats_from_env`] for the node URL.
+pub const URL_ENV: &str = "BLOCKSTATS_URL";
+
+/// The node URL used when none is specified.
+pub const DEFAULT_URL: &str = "ws://localhost:9944/";
+
 /// 50% of what is stored in configuration::activeConfig::maxPovSize at the relay chain.
 const POV_MAX: u64 = 5_242_880 / 2;
 
 /// Statistics regarding a specific block.
 ///
+/// All percentage helpers are relative to the limits stored in the block itself, like
+/// [`Self::max_weight`]. These are the limits of the runtime that built the block and
+/// are refreshed on every runtime upgrade. So while raw weights of blocks before and
+/// after an upgrade that changed the weight constants can't be compared, their
+/// percentages can.
+///
 /// Use the custom [`fmt::Display`] implementation to pretty print it.
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockStats {
     /// The block hash.
     pub hash: H256,
     /// The block number.
     pub number: u32,
+    /// The state root from the block header.
+    pub state_root: H256,
+    /// The extrinsics root from the block header.
+    pub extrinsics_root: H256,
+    /// The `spec_version` of the runtime that produced this block.
+    ///
+    /// A change between two consecutive blocks marks the block at which a runtime
+    /// upgrade took effect.
+    pub spec_version: u32,
+    /// The `transaction_version` of the runtime that produced this block.
+    pub transaction_version: u32,
     /// Total length of the PoV.
     ///
     /// PoV is the complete data that is send by the collator to the relay chain validator.
     /// In case of cumulus based chains this includes the storage proof and the block itself.
-    pub pov_len: u64,
-    /// Size of the storage proof in bytes.
-    pub witness_len: u64,
-    /// Size of the block in bytes.
-    pub len: u64,
+    pub pov_len: Bytes,
+    /// Size of the storage proof.
+    pub witness_len: Bytes,
+    /// Number of trie nodes in the storage proof.
+    ///
+    /// Only available if the [`StatsSource`] can count them, see
+    /// [`StatsSource::witness_node_count`]. None of the sources of this crate do, so this
+    /// is always `None` for them.
+    pub witness_nodes: Option<u64>,
+    /// Size of the block.
+    pub len: Bytes,
     /// Overall weight used by the block.
     pub weight: Weight,
+    /// The weight used by the block for each dispatch class.
+    ///
+    /// Their sum is [`Self::weight`].
+    pub class_weight: PerDispatchClass<Weight>,
+    /// [`Self::weight`] without the weight of extrinsics considered system overhead.
+    ///
+    /// The weight of extrinsics of the pallets passed to
+    /// [`StatsSubscriptionBuilder::system_overhead_pallets`] is subtracted. `None` if no
+    /// pallets were configured.
+    pub user_weight: Option<Weight>,
     /// Number of extrinsics in a block.
     pub num_extrinsics: u64,
+    /// Number of extrinsics that paid a transaction fee.
+    ///
+    /// Inherents and extrinsics that are free of charge are not counted. This sets apart
+    /// actual usage from the chain's own overhead. Only available if events are
+    /// decoded, see [`StatsSubscriptionBuilder::decode_events`].
+    pub num_fee_paying: Option<u64>,
+    /// Number of signed extrinsics, i.e. transactions submitted by users.
+    ///
+    /// Only available if they are counted, see [`StatsSubscriptionBuilder::count_signed`].
+    pub num_signed: Option<u64>,
+    /// The weight of the heaviest extrinsic of each dispatch class.
+    ///
+    /// Compare against the `max_extrinsic` values in [`Self::class_limits`] to find out
+    /// whether a bigger extrinsic would still fit, see
+    /// [`Self::max_extrinsic_fill_percent`]. Only available if events are decoded, see
+    /// [`StatsSubscriptionBuilder::decode_events`].
+    pub largest_extrinsic_weight: Option<PerDispatchClass<Weight>>,
+    /// The weight of every extrinsic in block order.
+    ///
+    /// Feed them to a [`WeightFlamegraph`](aggregate::WeightFlamegraph) to see which
+    /// calls use up the weight. Only available if events are decoded, see
+    /// [`StatsSubscriptionBuilder::decode_events`].
+    pub call_weights: Option<Vec<CallWeight>>,
+    /// Number of upward messages sent to the relay chain.
+    ///
+    /// Cross-chain messages add to the PoV and the bandwidth used at the relay chain.
+    /// Only available if messages are counted, see
+    /// [`StatsSubscriptionBuilder::count_messages`].
+    pub ump_messages: Option<u64>,
+    /// Number of downward messages received from the relay chain.
+    ///
+    /// Only available if messages are counted, see
+    /// [`StatsSubscriptionBuilder::count_messages`].
+    pub dmp_messages: Option<u64>,
+    /// Number of horizontal messages sent to other parachains.
+    ///
+    /// Only available if messages are counted, see
+    /// [`StatsSubscriptionBuilder::count_messages`].
+    pub hrmp_messages: Option<u64>,
+    /// The ID of the parachain as stored in `ParachainInfo::ParachainId`.
+    ///
+    /// `None` on relay and solo chains which don't have this pallet.
+    pub para_id: Option<u32>,
+    /// The authority that produced this block.
+    ///
+    /// Only available if authors are decoded, see
+    /// [`StatsSubscriptionBuilder::decode_author`].
+    pub author: Option<AccountId32>,
     /// The maximum allowed PoV size.
     ///
     /// Please note that this value is hardcoded to the value that is currently configured
     /// value in polkadot. It is stored in the `configuration::activeConfig::maxPovSize`
-    /// storage item of the relay chain.
-    pub max_pov: u64,
+    /// storage item of the relay chain. Pass a client to
+    /// [`StatsSubscriptionBuilder::relay_client`] to read it from there instead.
+    pub max_pov: Bytes,
+    /// Whether the PoV uses more of [`Self::max_pov`] than the configured budget.
+    ///
+    /// Only available if a budget is configured, see
+    /// [`StatsSubscriptionBuilder::soft_pov_budget`]. Use [`Self::is_over_soft_budget`]
+    /// to compare against any other budget.
+    pub over_soft_budget: Option<bool>,
     /// The maximum allowed weight.
     ///
     /// Please note that this is the overall weight disregarding any weight classes. It
-    /// is usually never reached even in a chain that is at capacity.
+    /// is usually never reached even in a chain that is at capacity. Taken from the
+    /// runtime version that built this block.
     pub max_weight: Weight,
+    /// The weight limits configured for each dispatch class.
+    ///
+    /// Useful to find out why an extrinsic was too big to be included into any block.
+    pub class_limits: PerDispatchClass<WeightsPerClass>,
+    /// The weight every block uses for its own overhead. Part of [`Self::weight`].
+    pub base_block: Weight,
+    /// How long it took to fetch the data for this block from the node.
+    ///
+    /// This is about the performance of the node's RPC and not the chain itself.
+    pub fetch_duration: Duration,
+    /// How long it took from the block arriving on the subscription until its stats
+    /// were complete.
+    ///
+    /// Unlike [`Self::fetch_duration`] this includes the time the block waited for
+    /// earlier blocks to be fetched. Steadily growing values mean that the RPC can't
+    /// keep up with the chain. Zero for blocks that weren't received via a subscription.
+    pub lag: Duration,
+    /// The local wall-clock time at which the block was received from the node.
+    ///
+    /// Unlike the on-chain `Timestamp::Now` this allows correlating blocks with local
+    /// logs and measuring how long blocks take to propagate to the node. `None` for
+    /// blocks that weren't received via a subscription or polling.
+    pub received_at: Option<SystemTime>,
+    /// How many blocks this block is ahead of the latest finalized block when it arrived.
+    ///
+    /// A growing value means that finality stalls while blocks are still produced.
+    /// Only available if enabled by [`StatsSubscriptionBuilder::track_finality`] and
+    /// once the first finalized block arrived.
+    pub finality_lag: Option<u32>,
+}
+
+impl BlockStats {
+    /// Used `Normal` class weight as a percentage of the class' `max_total`.
+    ///
+    /// Most of the block is reserved for normal extrinsics but the rest is kept for
+    /// operational and mandatory ones. Hence this shows congestion more accurately than
+    /// comparing against `max_block`. The more exhausted of both weight components is
+    /// used. `None` if the normal class has no limit of its own.
+    pub fn normal_class_fill_percent(&self) -> Option<Percent> {
+        let max = self.class_limits.normal.max_total?;
+        Some(Percent::of_weight(self.class_weight.normal, max))
+    }
+
+    /// How close the heaviest extrinsic came to the `max_extrinsic` limit of its class.
+    ///
+    /// The most exhausted weight component of any class is used. Values close to 100%
+    /// mean that a slightly bigger extrinsic would have been rejected. `None` if events
+    /// aren't decoded or no class limits single extrinsics.
+    pub fn max_extrinsic_fill_percent(&self) -> Option<Percent> {
+        let largest = self.largest_extrinsic_weight?;
+        let limits = self.class_limits;
+        [
+            (largest.normal, limits.normal.max_extrinsic),
+            (largest.operational, limits.operational.max_extrinsic),
+            (largest.mandatory, limits.mandatory.max_extrinsic),
+        ]
+        .into_iter()
+        .filter_map(|(used, max)| Some(Percent::of_weight(used, max?)))
+        .reduce(Percent::max)
+    }
+
+    /// Whether an extrinsic used at least `threshold` of its class' `max_extrinsic`.
+    ///
+    /// See [`Self::max_extrinsic_fill_percent`].
+    pub fn near_max_extrinsic(&self, threshold: Percent) -> bool {
+        self.max_extrinsic_fill_percent()
+            .is_some_and(|fill| fill >= threshold)
+    }
+
+    /// The PoV size split into its parts.
+    pub fn pov(&self) -> PovBreakdown {
+        PovBreakdown {
+            total: self.pov_len,
+            witness: self.witness_len,
+            block: self.len,
+        }
+    }
+
+    /// PoV size as a percentage of [`Self::max_pov`].
+    pub fn pov_percent(&self) -> Percent {
+        Percent::of(self.pov_len, self.max_pov)
+    }
+
+    /// Used `ref_time` as a percentage of the one in [`Self::max_weight`].
+    pub fn ref_time_percent(&self) -> Percent {
+        Percent::of(self.weight.ref_time, self.max_weight.ref_time)
+    }
+
+    /// Used `proof_size` as a percentage of the one in [`Self::max_weight`].
+    ///
+    /// Always zero on runtimes using the legacy one dimensional weight.
+    pub fn proof_size_percent(&self) -> Percent {
+        Percent::of(self.weight.proof_size, self.max_weight.proof_size)
+    }
+
+    /// PoV size relative to [`Self::max_pov`] between 0.0 and 1.0.
+    ///
+    /// Unlike [`Self::pov_percent`] this is clamped to 1.0 in case the limit is exceeded.
+    pub fn pov_ratio(&self) -> f64 {
+        ratio(self.pov_len, self.max_pov)
+    }
+
+    /// Used `ref_time` relative to [`Self::max_weight`] between 0.0 and 1.0.
+    ///
+    /// Clamped to 1.0 which can be exceeded by overweight mandatory extrinsics.
+    pub fn ref_time_ratio(&self) -> f64 {
+        ratio(self.weight.ref_time, self.max_weight.ref_time)
+    }
+
+    /// Used `proof_size` relative to [`Self::max_weight`] between 0.0 and 1.0.
+    ///
+    /// Clamped to 1.0 which can be exceeded by overweight mandatory extrinsics.
+    pub fn proof_size_ratio(&self) -> f64 {
+        ratio(self.weight.proof_size, self.max_weight.proof_size)
+    }
+
+    /// How close the block is to its limits.
+    ///
+    /// This is the fill level of the most exhausted resource out of the PoV size and
+    /// both weight components as this is the one limiting the block.
+    pub fn capacity_score(&self) -> Percent {
+        self.pov_percent()
+            .max(self.ref_time_percent())
+            .max(self.proof_size_percent())
+    }
+
+    /// The weight the block would use if its extrinsics didn't do anything.
+    ///
+    /// This is [`Self::base_block`] plus the `Normal` class' `base_extrinsic` for every
+    /// extrinsic. The metadata contains no benchmarked weights of individual calls so
+    /// this is the only expectation that can be derived without them.
+    pub fn base_weight(&self) -> Weight {
+        let base_extrinsic = self.class_limits.normal.base_extrinsic;
+        self.base_block
+            + Weight::from_parts(
+                base_extrinsic.ref_time.saturating_mul(self.num_extrinsics),
+                base_extrinsic
+                    .proof_size
+                    .saturating_mul(self.num_extrinsics),
+            )
+    }
+
+    /// How much more `ref_time` the block used than its [base weight](Self::base_weight).
+    ///
+    /// For example 300% means the block used four times its base weight. A sudden
+    /// change while the extrinsics stay the same hints at changed weights of the
+    /// dispatched calls, e.g. after a runtime upgrade. Zero if the base weight is zero.
+    pub fn ref_time_above_base_percent(&self) -> Percent {
+        let base = self.base_weight().ref_time;
+        Percent::of(self.weight.ref_time.saturating_sub(base), base)
+    }
+
+    /// Whether `proof_size` is more exhausted than `ref_time`.
+    ///
+    /// On many cumulus based chains the storage proof limits the block before the
+    /// computation does. See [`aggregate::WindowStats::proof_bound_fraction`] for how
+    /// often this happens over a number of blocks.
+    pub fn proof_bound_first(&self) -> bool {
+        self.proof_size_percent() > self.ref_time_percent()
+    }
+
+    /// Percentage of the used weight that was used by `Mandatory` extrinsics.
+    ///
+    /// These are mostly inherents like the parachain inherent that every block has to
+    /// include. Their weight is a tax on the block's capacity that is never available to
+    /// users. The more dominant of both weight components is used. Zero for a block that
+    /// used no weight at all.
+    ///
+    /// ```
+    /// use blockstats::{BlockStats, PerDispatchClass, Weight};
+    ///
+    /// let class_weight = PerDispatchClass {
+    ///     normal: Weight::from_parts(300, 10),
+    ///     operational: Weight::from_parts(0, 0),
+    ///     mandatory: Weight::from_parts(100, 30),
+    /// };
+    /// let block = BlockStats {
+    ///     weight: Weight::from_parts(400, 40),
+    ///     class_weight,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(block.mandatory_overhead_percent().as_percent(), 75.0);
+    /// ```
+    pub fn mandatory_overhead_percent(&self) -> Percent {
+        Percent::of_weight(self.class_weight.mandatory, self.weight)
+    }
+
+    /// Percentage of the PoV that is taken up by the storage proof.
+    ///
+    /// This is overhead that doesn't contain any user data. High values mean that the
+    /// chain pays a lot for storage proofs relative to the actual transactions.
+    pub fn witness_overhead_percent(&self) -> Percent {
+        Percent::of(self.witness_len, self.pov_len)
+    }
+
+    /// Average size of a trie node in the storage proof.
+    ///
+    /// `None` if [`Self::witness_nodes`] isn't available or zero.
+    pub fn avg_witness_node_len(&self) -> Option<Bytes> {
+        self.witness_len.checked_div(self.witness_nodes?)
+    }
+
+    /// Whether the storage proof consists of unusually many small trie nodes.
+    ///
+    /// This is a heuristic. Relay chain validators have to decode every node of the
+    /// proof, so a PoV well below the size limit can still take long to validate when
+    /// it is made up of many small nodes. A block is flagged if its
+    /// [average node size](Self::avg_witness_node_len) is below `min_avg_node_len`.
+    /// Which threshold is unusual depends on the chain and is best derived from its
+    /// normal blocks. Always `false` if the nodes aren't counted.
+    ///
+    /// ```
+    /// use blockstats::{BlockStats, Bytes};
+    ///
+    /// let block = BlockStats { witness_len: Bytes(64_000), witness_nodes: Some(2_000), ..Default::default() };
+    /// assert_eq!(block.avg_witness_node_len(), Some(Bytes(32)));
+    /// assert!(block.is_witness_node_heavy(Bytes(64)));
+    /// assert!(!block.is_witness_node_heavy(Bytes(16)));
+    /// assert!(!BlockStats { witness_nodes: None, ..block }.is_witness_node_heavy(Bytes(64)));
+    /// ```
+    pub fn is_witness_node_heavy(&self, min_avg_node_len: Bytes) -> bool {
+        self.avg_witness_node_len()
+            .is_some_and(|len| len < min_avg_node_len)
+    }
+
+    /// Bytes of the block per byte of PoV.
+    ///
+    /// The block is the useful work while the rest of the PoV is proof overhead. A
+    /// chain that sends 2 MiB of PoV to include 50 KiB of transactions has an
+    /// efficiency of about 0.024. Zero if the PoV is empty.
+    pub fn pov_efficiency(&self) -> f64 {
+        fraction(self.len, self.pov_len)
+    }
+
+    /// Whether the block contains no extrinsics at all.
+    ///
+    /// All helpers that are computed per extrinsic return `None` for such a block.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blockstats::{
+    ///     aggregate::{SessionTotals, WindowStats},
+    ///     BlockStats, Bytes,
+    /// };
+    /// use std::time::Duration;
+    ///
+    /// let block = BlockStats {
+    ///     pov_len: Bytes(1024),
+    ///     max_pov: Bytes(4096),
+    ///     num_fee_paying: Some(0),
+    ///     num_signed: Some(0),
+    ///     ..Default::default()
+    /// };
+    /// assert!(block.is_empty());
+    /// assert!(block.is_idle());
+    /// assert_eq!(block.avg_extrinsic_len(), None);
+    /// assert_eq!(block.avg_extrinsic_weight(), None);
+    /// assert_eq!(block.fee_paying_fraction(), None);
+    /// assert_eq!(block.pov_remaining(), Bytes(3072));
+    /// assert_eq!(block.extrinsic_headroom(), None);
+    /// assert_eq!(block.effective_tps(Duration::from_secs(6)), Some(0.0));
+    ///
+    /// // The aggregates of only such blocks.
+    /// let mut window = WindowStats::new(10, Duration::from_secs(6));
+    /// let mut totals = SessionTotals::default();
+    /// for _ in 0..3 {
+    ///     window.push(block.clone());
+    ///     totals.push(&block);
+    /// }
+    /// assert_eq!(window.effective_tps(), Some(0.0));
+    /// assert_eq!(window.avg_pov_len(), Bytes(1024));
+    /// assert_eq!(totals.idle_blocks(), 3);
+    /// assert_eq!(totals.avg_pov_len(), Bytes(1024));
+    /// assert_eq!(totals.avg_pov_len_excluding_idle(), Bytes(0));
+    /// assert_eq!(totals.avg_ref_time_excluding_idle(), 0);
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.num_extrinsics == 0
+    }
+
+    /// Whether the PoV uses more than `budget` of [`Self::max_pov`].
+    ///
+    /// Meant for an internal alarm below the hard limit, e.g. at 80%. Blocks of a
+    /// subscription are already compared against the budget configured with
+    /// [`StatsSubscriptionBuilder::soft_pov_budget`], see [`Self::over_soft_budget`].
+    ///
+    /// ```
+    /// use blockstats::{BlockStats, Bytes, Percent};
+    ///
+    /// let stats = BlockStats { pov_len: Bytes(85), max_pov: Bytes(100), ..Default::default() };
+    /// assert!(stats.is_over_soft_budget(Percent::from_percent(80.0)));
+    /// assert!(!stats.is_over_soft_budget(Percent::from_percent(90.0)));
+    /// ```
+    pub fn is_over_soft_budget(&self, budget: Percent) -> bool {
+        self.pov_percent() > budget
+    }
+
+    /// Whether the block contains no extrinsics submitted by users.
+    ///
+    /// Uses [`Self::num_fee_paying`] if events are decoded as the inherents every
+    /// parachain block contains don't pay fees. Otherwise falls back to
+    /// [`Self::num_signed`] as inherents are never signed. Only if neither is available
+    /// it falls back to [`Self::is_empty`], which is never true on a parachain.
+    pub fn is_idle(&self) -> bool {
+        match (self.num_fee_paying, self.num_signed) {
+            (Some(fee_paying), _) => fee_paying == 0,
+            (None, Some(signed)) => signed == 0,
+            (None, None) => self.is_empty(),
+        }
+    }
+
+    /// Average size of an extrinsic.
+    ///
+    /// `None` if the block [is empty](Self::is_empty).
+    pub fn avg_extrinsic_len(&self) -> Option<Bytes> {
+        self.per_extrinsic(self.len.into()).map(Bytes)
+    }
+
+    /// Average weight used by an extrinsic.
+    ///
+    /// `None` if the block [is empty](Self::is_empty).
+    pub fn avg_extrinsic_weight(&self) -> Option<Weight> {
+        Some(Weight::from_parts(
+            self.per_extrinsic(self.weight.ref_time)?,
+            self.per_extrinsic(self.weight.proof_size)?,
+        ))
+    }
+
+    /// Share of the extrinsics that paid a fee between 0.0 and 1.0.
+    ///
+    /// `None` if the block [is empty](Self::is_empty) or events aren't decoded.
+    pub fn fee_paying_fraction(&self) -> Option<f64> {
+        let fee_paying = self.num_fee_paying?;
+        (!self.is_empty()).then(|| fraction(fee_paying, self.num_extrinsics))
+    }
+
+    /// Bytes that could still be added to the PoV before reaching [`Self::max_pov`].
+    pub fn pov_remaining(&self) -> Bytes {
+        self.max_pov.saturating_sub(self.pov_len)
+    }
+
+    /// Weight that could still be used before reaching [`Self::max_weight`].
+    pub fn weight_remaining(&self) -> Weight {
+        self.max_weight.saturating_sub(self.weight)
+    }
+
+    /// How many more extrinsics of [average size](Self::avg_extrinsic_len) would fit
+    /// into the PoV.
+    ///
+    /// `None` if the block [is empty](Self::is_empty).
+    pub fn extrinsic_headroom(&self) -> Option<u64> {
+        self.pov_remaining()
+            .as_u64()
+            .checked_div(self.avg_extrinsic_len()?.as_u64())
+    }
+
+    /// `value` divided by the number of extrinsics.
+    ///
+    /// Every helper computing a per extrinsic value goes through this so that empty
+    /// blocks are handled the same everywhere.
+    fn per_extrinsic(&self, value: u64) -> Option<u64> {
+        value.checked_div(self.num_extrinsics)
+    }
+
+    /// Signed extrinsics per second if every block was like this one.
+    ///
+    /// `block_interval` is the time between two blocks of the chain. `None` if signed
+    /// extrinsics aren't counted.
+    pub fn effective_tps(&self, block_interval: Duration) -> Option<f64> {
+        let secs = block_interval.as_secs_f64();
+        let num_signed = self.num_signed?;
+        if secs == 0.0 {
+            return Some(0.0);
+        }
+        Some(num_signed as f64 / secs)
+    }
+
+    /// Estimated bytes per second sent to the relay chain if every block was like this one.
+    ///
+    /// `block_interval` is the time between two blocks of the chain.
+    pub fn bandwidth_bps(&self, block_interval: Duration) -> f64 {
+        let secs = block_interval.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.pov_len.as_u64() as f64 / secs
+    }
+
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
 }
 
 impl fmt::Display for BlockStats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for BlockStats {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
         write!(
             f,
-            "{:04}: PoV Size={:04}KiB({:03}%) Weight RefTime={:07}ms({:03}%) Weight ProofSize={:04}KiB({:03}%) Witness={:04}KiB Block={:04}KiB NumExtrinsics={:04}",
+            "{:04}{}: PoV Size={}({}%) Weight RefTime={:07}ms({}%) Weight ProofSize={}({}%) Witness={}({}%) Block={} NumExtrinsics={:04} Capacity={}",
             self.number,
-            self.pov_len / 1024,
-            self.pov_len * 100 / self.max_pov,
+            config.hash(self.hash),
+            config.size(self.pov_len),
+            config.percent(self.pov_percent()),
             self.weight.ref_time / 1_000_000_000,
-            self.weight.ref_time * 100 / self.max_weight.ref_time,
-            self.weight.proof_size / 1024,
-            self.weight.proof_size * 100 / self.max_weight.proof_size,
-            self.witness_len / 1024,
-            self.len / 1024,
+            config.percent(self.ref_time_percent()),
+            config.size(self.weight.proof_size),
+            config.percent(self.proof_size_percent()),
+            config.size(self.witness_len),
+            config.rounded_percent(self.witness_overhead_percent()),
+            config.size(self.len),
             self.num_extrinsics,
+            config.rounded_percent(self.capacity_score()),
         )
     }
 }
 
+/// What the PoV of a block consists of.
+///
+/// Returned by [`BlockStats::pov`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PovBreakdown {
+    /// Total length of the PoV.
+    pub total: Bytes,
+    /// Size of the storage proof.
+    pub witness: Bytes,
+    /// Size of the block.
+    pub block: Bytes,
+}
+
+impl PovBreakdown {
+    /// Share of the storage proof in the PoV between 0.0 and 1.0.
+    pub fn witness_fraction(&self) -> f64 {
+        fraction(self.witness, self.total)
+    }
+
+    /// Share of the block in the PoV between 0.0 and 1.0.
+    pub fn block_fraction(&self) -> f64 {
+        fraction(self.block, self.total)
+    }
+}
+
+/// A percentage as returned by the fill level helpers of [`BlockStats`].
+///
+/// Can exceed 100% when a limit is overrun, e.g. by mandatory extrinsics. Displayed
+/// with one decimal place like `42.5%`.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::Percent;
+///
+/// let half = Percent::from_ratio(0.5);
+/// assert_eq!(half.as_percent(), 50.0);
+/// assert_eq!(half.as_ratio(), 0.5);
+/// assert_eq!(half.to_string(), "50.0%");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Percent(f64);
+
+impl Percent {
+    /// Create from a value between 0.0 and 100.0.
+    pub fn from_percent(percent: f64) -> Self {
+        Self(percent)
+    }
+
+    /// Create from a value between 0.0 and 1.0.
+    pub fn from_ratio(ratio: f64) -> Self {
+        Self(ratio * 100.0)
+    }
+
+    /// The value between 0.0 and 1.0.
+    pub fn as_ratio(self) -> f64 {
+        self.0 / 100.0
+    }
+
+    /// The value between 0.0 and 100.0.
+    pub fn as_percent(self) -> f64 {
+        self.0
+    }
+
+    /// The larger of both percentages.
+    pub fn max(self, other: Self) -> Self {
+        Self(self.0.max(other.0))
+    }
+
+    /// `value` as a percentage of `max` or zero if there is no limit.
+    fn of(value: impl Into<u64>, max: impl Into<u64>) -> Self {
+        Self::from_ratio(fraction(value, max))
+    }
+
+    /// The more exhausted of both weight components.
+    fn of_weight(used: Weight, max: Weight) -> Self {
+        Self::of(used.ref_time, max.ref_time).max(Self::of(used.proof_size, max.proof_size))
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}%", self.0)
+    }
+}
+
+/// `part / total` or zero if `total` is zero.
+fn fraction(part: impl Into<u64>, total: impl Into<u64>) -> f64 {
+    let total = total.into();
+    if total == 0 {
+        return 0.0;
+    }
+    part.into() as f64 / total as f64
+}
+
+/// `duration` in microseconds, saturated at `u64::MAX`.
+///
+/// Shared by the sinks that store durations as integers so that they agree on huge
+/// values.
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+/// Like [`fraction`] but never more than 1.0.
+fn ratio(value: impl Into<u64>, max: impl Into<u64>) -> f64 {
+    fraction(value, max).min(1.0)
+}
+
+/// The stats of consecutive blocks as returned by the subscription functions.
+///
+/// A named [`Stream`], and hence [`TryStream`](futures::TryStream), that can be stored
+/// in a struct without boxing it yourself. It is `Send` and `Unpin`.
+///
+/// # Example
+///
+/// ```no_run
+/// use blockstats::{BlockStatsError, BlockStatsStream};
+/// use futures::TryStreamExt;
+///
+/// struct Monitor {
+///     stats: BlockStatsStream,
+/// }
+///
+/// impl Monitor {
+///     async fn next_block_number(&mut self) -> Result<Option<u32>, BlockStatsError> {
+///         Ok(self.stats.try_next().await?.map(|stats| stats.number))
+///     }
+/// }
+/// ```
+pub struct BlockStatsStream(BoxStream<'static, Result<BlockStats, BlockStatsError>>);
+
+impl BlockStatsStream {
+    /// Wrap any stream of stats, e.g. one adapted with the [`combinators`].
+    pub fn new(
+        stats: impl Stream<Item = Result<BlockStats, BlockStatsError>> + Send + 'static,
+    ) -> Self {
+        Self(stats.boxed())
+    }
+}
+
+impl Stream for BlockStatsStream {
+    type Item = Result<BlockStats, BlockStatsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl fmt::Debug for BlockStatsStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BlockStatsStream").finish_non_exhaustive()
+    }
+}
+
 /// Connect to the specified node and listen for new blocks.
 ///
 /// The `url` needs to be a websocket so that we can subscribe to new blocks.
-pub async fn subscribe_stats(
-    url: &str,
-) -> Result<impl TryStream<Ok = BlockStats, Error = Error> + Unpin, Error> {
-    let rpc_client = RpcClient::from_url(url).await?;
-    subscribe_stats_with_client(rpc_client).await
+pub async fn subscribe_stats(url: &str) -> Result<BlockStatsStream, BlockStatsError> {
+    StatsSubscriptionBuilder::new(url).subscribe().await
+}
+
+/// Like [`subscribe_stats`] but takes the URL from the [`URL_ENV`] environment variable.
+///
+/// Falls back to [`DEFAULT_URL`] when the variable isn't set.
+pub async fn subscribe_stats_from_env() -> Result<BlockStatsStream, BlockStatsError> {
+    let url = std::env::var(URL_ENV).unwrap_or_else(|_| DEFAULT_URL.to_owned());
+    subscribe_stats(&url).await
+}
+
+/// Identifies the chain an item of [`subscribe_multi`] belongs to.
+pub type ChainId = String;
+
+/// Listen for new blocks of several chains at once.
+///
+/// `chains` pairs an identifier of your choice with the websocket URL of a node of that
+/// chain. The subscriptions are merged into a single stream in which every item is
+/// labeled with the identifier of its chain. Connecting happens while the stream is
+/// polled. Errors are labeled the same way and never affect the other chains: a chain
+/// whose node can't be reached yields a single error and contributes nothing after
+/// that while the blocks of the other chains keep coming. The stream ends once all
+/// subscriptions ended.
+pub fn subscribe_multi(
+    chains: Vec<(ChainId, String)>,
+) -> impl Stream<Item = (ChainId, Result<BlockStats, BlockStatsError>)> + Send + Unpin {
+    stream::select_all(chains.into_iter().map(|(chain, url)| {
+        stream::once(async move { subscribe_stats(&url).await })
+            .flat_map(|subscription| match subscription {
+                Ok(stats) => stats.left_stream(),
+                Err(err) => stream::once(future::ready(Err(err))).right_stream(),
+            })
+            .map(move |stats| (chain.clone(), stats))
+            .boxed()
+    }))
 }
 
 /// Connect to the specified node and listen for new blocks using OnlineClient.
 pub async fn subscribe_stats_with_client(
     rpc_client: RpcClient,
-) -> Result<impl TryStream<Ok = BlockStats, Error = Error> + Unpin, Error> {
-    let client = OnlineClient::<DefaultConfig>::from_rpc_client(rpc_client.clone()).await?;
-    let blocks = client.blocks().subscribe_best().await?;
-
-    let max_block_weights: BlockWeights = {
-        let metadata = client.metadata();
-        let pallet = metadata.pallet_by_name_err("System")?;
-        let constant_name = "BlockWeights";
-        let constant = pallet
-            .constant_by_name(constant_name)
-            .ok_or_else(|| MetadataError::ConstantNameNotFound(constant_name.to_owned()))?;
-        codec::Decode::decode(&mut &constant.value()[..])?
+) -> Result<BlockStatsStream, BlockStatsError> {
+    StatsSubscriptionBuilder::with_client(rpc_client)
+        .subscribe()
+        .await
+}
+
+/// Listen for new blocks of an arbitrary [`StatsSource`].
+pub async fn subscribe_stats_with_source<S: StatsSource>(
+    source: S,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    subscribe_with_fetcher(StatsFetcher::new(source)).await
+}
+
+/// Listen for new blocks using an already configured fetcher.
+pub(crate) async fn subscribe_with_fetcher<S: StatsSource>(
+    fetcher: StatsFetcher<S>,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let blocks = fetcher.source.subscribe_blocks().await?;
+    let blocks = if fetcher.track_finality {
+        let finalized = fetcher.source.subscribe_finalized().await?;
+        let mut latest_finalized = None;
+        stream::select(
+            blocks
+                .map(|block| Some(block.map(|block| (block, true))))
+                // Marks the end of the new blocks as the finalized ones go on after that.
+                .chain(stream::once(future::ready(None))),
+            finalized.map(|block| Some(block.map(|block| (block, false)))),
+        )
+        .take_while(|block| future::ready(block.is_some()))
+        .filter_map(future::ready)
+        .try_filter_map(move |((hash, header), best)| {
+            if !best {
+                latest_finalized = Some(header.number);
+                return future::ready(Ok(None));
+            }
+            let finality_lag = latest_finalized.map(|number| header.number.saturating_sub(number));
+            future::ready(Ok(Some((hash, header, finality_lag))))
+        })
+        .boxed()
+    } else {
+        blocks.map_ok(|(hash, header)| (hash, header, None)).boxed()
     };
 
-    Ok(Box::pin(blocks.map_err(Into::into).and_then(
-        move |block| {
-            let client = client.clone();
-            let rpc_methods = LegacyRpcMethods::<DefaultConfig>::new(rpc_client.clone());
-
-            let block_weight_address =
-                Address::<StaticStorageMapKey, PerDispatchClass<Weight>, Yes, Yes, ()>::new_static(
-                    "System",
-                    "BlockWeight",
-                    vec![],
-                    Default::default(),
-                )
-                .unvalidated();
+    let (arrivals, blocks) = Arrivals::new(blocks);
+    let mut prev_number = None;
+    let stats = blocks
+        .map_ok(move |(block, arrived, received_at)| {
+            let (hash, header, finality_lag) = block;
+            let number = header.number;
+            if let Some(prev) = prev_number.filter(|prev| number <= *prev) {
+                tracing::warn!(
+                    "Best block went from #{prev} to #{number}. Likely a reorg of depth {}.",
+                    prev - number + 1,
+                );
+            }
+            prev_number = Some(number);
+            let fetcher = fetcher.clone();
             async move {
-                let stats = rpc_methods
-                    .dev_get_block_stats(block.hash())
+                let mut stats = fetcher.fetch(hash, &header).await?;
+                stats.lag = arrived.elapsed();
+                stats.received_at = Some(received_at);
+                stats.finality_lag = finality_lag;
+                Ok(stats)
+            }
+        })
+        .try_buffered(LIVE_CONCURRENCY);
+
+    let mut prev_lag = Duration::ZERO;
+    let mut lag_growing = 0;
+    let stats = arrivals.drive(stats).inspect_ok(move |stats| {
+        lag_growing = if stats.lag > prev_lag {
+            lag_growing + 1
+        } else {
+            0
+        };
+        prev_lag = stats.lag;
+        if lag_growing == LAG_WARN_BLOCKS {
+            tracing::warn!(
+                "Lag grew for {LAG_WARN_BLOCKS} blocks in a row to {:?} at #{}. The RPC \
+                     is not keeping up with the chain.",
+                stats.lag,
+                stats.number,
+            );
+        }
+    });
+    Ok(BlockStatsStream::new(stats))
+}
+
+/// Stamps new blocks with the time they arrived on the subscription.
+///
+/// Blocks are taken off the subscription whenever the stats are polled, not only when
+/// there is room for fetching another one. Otherwise they would wait on the
+/// subscription while earlier blocks are fetched, and their lag would be
+/// underestimated. The blocks taken off but not fetched yet are buffered.
+struct Arrivals<T>(Arc<Mutex<ArrivalQueue<T>>>);
+
+/// The state shared by [`Arrivals`] and the stream of stamped blocks.
+struct ArrivalQueue<T> {
+    blocks: stream::Fuse<BoxStream<'static, Result<T, BlockStatsError>>>,
+    arrived: VecDeque<Arrived<T>>,
+}
+
+/// A block together with the time it arrived as [`Instant`] and wall-clock time.
+type Arrived<T> = Result<(T, Instant, SystemTime), BlockStatsError>;
+
+impl<T: Send + 'static> Arrivals<T> {
+    /// Stamp `blocks`. The stamped blocks need to be [driven](Self::drive).
+    fn new(
+        blocks: BoxStream<'static, Result<T, BlockStatsError>>,
+    ) -> (Self, BoxStream<'static, Arrived<T>>) {
+        let queue = Arc::new(Mutex::new(ArrivalQueue {
+            blocks: blocks.fuse(),
+            arrived: VecDeque::new(),
+        }));
+        let stamped = queue.clone();
+        let stamped = stream::poll_fn(move |cx| {
+            let mut queue = stamped.lock().expect("never poisoned");
+            queue.receive(cx);
+            match queue.arrived.pop_front() {
+                Some(block) => Poll::Ready(Some(block)),
+                None if queue.blocks.is_terminated() => Poll::Ready(None),
+                None => Poll::Pending,
+            }
+        });
+        (Self(queue), stamped.boxed())
+    }
+
+    /// Receive new blocks every time `stats`, which consumes the stamped blocks, is polled.
+    fn drive<S: Stream + Unpin>(self, mut stats: S) -> impl Stream<Item = S::Item> {
+        stream::poll_fn(move |cx| {
+            self.0.lock().expect("never poisoned").receive(cx);
+            stats.poll_next_unpin(cx)
+        })
+    }
+}
+
+impl<T> ArrivalQueue<T> {
+    /// Take every block that is ready off the subscription.
+    fn receive(&mut self, cx: &mut Context) {
+        while let Poll::Ready(Some(block)) = self.blocks.poll_next_unpin(cx) {
+            self.arrived
+                .push_back(block.map(|block| (block, Instant::now(), SystemTime::now())));
+        }
+    }
+}
+
+/// Connect to the specified node and poll its best block every `interval`.
+///
+/// For nodes on which subscriptions are blocked, e.g. by a proxy, but `chain_getHeader`
+/// works. A block is emitted once when it is first seen as the best block. Blocks that
+/// were the best block only in between two polls are missed. Errors don't end the
+/// stream and polling continues.
+pub async fn poll_stats(
+    url: &str,
+    interval: Duration,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    poll_stats_with_client(rpc_client, interval).await
+}
+
+/// Like [`poll_stats`] but using an existing client.
+pub async fn poll_stats_with_client(
+    rpc_client: RpcClient,
+    interval: Duration,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let fetcher = StatsFetcher::connect(rpc_client).await?;
+    let header_fetcher = fetcher.clone();
+    let mut last = None;
+    Ok(BlockStatsStream::new(
+        ticks(interval)
+            .then(move |()| {
+                let fetcher = header_fetcher.clone();
+                async move {
+                    let header = fetcher
+                        .source
+                        .rpc_methods
+                        .chain_get_header(None)
+                        .await?
+                        .ok_or_else(|| BlockStatsError::UnexpectedResponse {
+                            method: "chain_getHeader",
+                            reason: "no best block".into(),
+                        })?;
+                    Ok((header.hash(), header, SystemTime::now()))
+                }
+            })
+            .try_filter(move |(hash, ..)| future::ready(last.replace(*hash) != Some(*hash)))
+            .and_then(move |(hash, header, received_at)| {
+                let fetcher = fetcher.clone();
+                async move {
+                    let mut stats = fetcher.fetch(hash, &header).await?;
+                    stats.received_at = Some(received_at);
+                    Ok(stats)
+                }
+            }),
+    ))
+}
+
+/// Yields right away and then every `interval`.
+///
+/// When the consumer takes longer than `interval` the next tick is yielded immediately
+/// instead of trying to catch up on the missed ones.
+fn ticks(interval: Duration) -> impl Stream<Item = ()> {
+    stream::unfold(Instant::now(), move |next| async move {
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            Delay::new(wait).await;
+        }
+        Some(((), (next + interval).max(Instant::now())))
+    })
+}
+
+/// How many new blocks are fetched in parallel when following the chain.
+const LIVE_CONCURRENCY: usize = 4;
+
+/// Warn when the lag grew for this many blocks in a row.
+const LAG_WARN_BLOCKS: usize = 5;
+
+/// The default number of blocks [`stats_for_range`] fetches in parallel.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Number of blocks whose hashes and weights [`stats_for_range`] requests with a single call.
+const RANGE_CHUNK_SIZE: u32 = 256;
+
+/// How many headers of a chunk [`stats_for_range`] requests at the same time.
+///
+/// Low enough to stay below the default per connection request buffer of substrate nodes.
+const HEADER_CONCURRENCY: usize = 32;
+
+/// Connect to the specified node and compute the stats for a range of existing blocks.
+///
+/// Up to `concurrency` blocks are fetched in parallel. The blocks are emitted in
+/// order of their block number regardless. Querying blocks that were pruned
+/// requires an archive node.
+///
+/// The block hashes and used weights are requested in batches of blocks. Nodes that
+/// don't expose `state_queryStorage` fall back to reading the weight per block. The
+/// headers of a batch are requested up front with many requests in flight at once, so
+/// that their round trips overlap instead of adding up.
+///
+/// A backfill of 10 000 blocks thus takes 40 calls of each `chain_getBlockHash` and
+/// `state_queryStorage` for the hashes and weights instead of 10 000 each. The calls
+/// that remain per block are `chain_getHeader`, `dev_getBlockStats` and
+/// `state_getRuntimeVersion`, as the node offers no way to request them for several
+/// blocks at once.
+///
+/// The weight limits are the ones of the runtime that built each block. Please note
+/// that `max_pov` is hardcoded, see [`BlockStats::max_pov`].
+pub async fn stats_for_range(
+    url: &str,
+    range: RangeInclusive<u32>,
+    concurrency: usize,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    stats_for_range_with_client(rpc_client, range, concurrency).await
+}
+
+/// Like [`stats_for_range`] but using an existing client.
+pub async fn stats_for_range_with_client(
+    rpc_client: RpcClient,
+    range: RangeInclusive<u32>,
+    concurrency: usize,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let fetcher = StatsFetcher::connect(rpc_client).await?;
+    let (start, end) = range.into_inner();
+    let chunks = (start..=end)
+        .step_by(RANGE_CHUNK_SIZE as usize)
+        .map(move |first| first..=first.saturating_add(RANGE_CHUNK_SIZE - 1).min(end));
+
+    let chunk_fetcher = fetcher.clone();
+    Ok(BlockStatsStream::new(
+        stream::iter(chunks)
+            .then(move |chunk| {
+                let fetcher = chunk_fetcher.clone();
+                async move { fetcher.resolve_chunk(chunk).await }
+            })
+            .map_ok(|blocks| stream::iter(blocks.into_iter().map(Ok)))
+            .try_flatten()
+            .map_ok(move |(hash, header, weight)| {
+                let fetcher = fetcher.clone();
+                async move { fetcher.fetch_with_weight(hash, &header, weight).await }
+            })
+            .try_buffered(concurrency.max(1)),
+    ))
+}
+
+/// Compute the stats of the blocks produced during the last `duration`.
+///
+/// The first block is estimated from `block_time`, the expected time between two blocks,
+/// and the current best block. The blocks are fetched like [`stats_for_range`] with
+/// [`DEFAULT_CONCURRENCY`]. When blocks were skipped during that time more than
+/// `duration` is covered. No blocks are returned if `duration` or `block_time` is zero.
+pub async fn stats_since(
+    rpc_client: RpcClient,
+    duration: Duration,
+    block_time: Duration,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let count = match block_time.as_secs_f64() {
+        secs if secs > 0.0 => (duration.as_secs_f64() / secs).ceil() as u32,
+        _ => 0,
+    };
+    if count == 0 {
+        return Ok(BlockStatsStream::new(stream::empty()));
+    }
+    let head = LegacyRpcMethods::<PolkadotConfig>::new(rpc_client.clone())
+        .chain_get_header(None)
+        .await?
+        .ok_or_else(|| BlockStatsError::UnexpectedResponse {
+            method: "chain_getHeader",
+            reason: "no best block".into(),
+        })?
+        .number;
+    let start = head.saturating_sub(count - 1);
+    stats_for_range_with_client(rpc_client, start..=head, DEFAULT_CONCURRENCY).await
+}
+
+/// Like [`stats_for_range_with_client`] but only using the `archive_unstable_*` RPC methods.
+///
+/// For archive nodes that have the legacy RPC methods disabled. See [`ArchiveSource`] for
+/// which values can't be reconstructed that way.
+pub async fn stats_for_range_with_archive(
+    rpc_client: RpcClient,
+    range: RangeInclusive<u32>,
+    concurrency: usize,
+) -> Result<BlockStatsStream, BlockStatsError> {
+    let fetcher = StatsFetcher::new(ArchiveSource::new(rpc_client).await?);
+    Ok(BlockStatsStream::new(
+        stream::iter(range)
+            .map(move |number| {
+                let fetcher = fetcher.clone();
+                async move {
+                    let hash = fetcher
+                        .source
+                        .block_hash(number)
+                        .await?
+                        .ok_or(BlockStatsError::UnknownBlockNumber(number))?;
+                    let header = fetcher.source.header(hash).await?;
+                    fetcher.fetch(hash, &header).await
+                }
+            })
+            .buffered(concurrency.max(1)),
+    ))
+}
+
+/// Connect to the specified node and compute the stats of a single block.
+///
+/// Pass `None` as `hash` to get the stats of the current best block.
+pub async fn stats_for_block(url: &str, hash: Option<H256>) -> Result<BlockStats, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    stats_for_block_with_client(rpc_client, hash).await
+}
+
+/// Like [`stats_for_block`] but using an existing client.
+pub async fn stats_for_block_with_client(
+    rpc_client: RpcClient,
+    hash: Option<H256>,
+) -> Result<BlockStats, BlockStatsError> {
+    StatsFetcher::connect(rpc_client)
+        .await?
+        .fetch_by_hash(hash)
+        .await
+}
+
+/// Connect to the specified node and compute the stats of the `count` most recent finalized blocks.
+///
+/// The blocks are found by walking the parent hashes starting at the finalized head.
+/// They are returned oldest first. Fewer blocks are returned when the chain is shorter.
+pub async fn stats_for_last_finalized(
+    url: &str,
+    count: usize,
+) -> Result<Vec<BlockStats>, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    stats_for_last_finalized_with_client(rpc_client, count).await
+}
+
+/// Like [`stats_for_last_finalized`] but using an existing client.
+pub async fn stats_for_last_finalized_with_client(
+    rpc_client: RpcClient,
+    count: usize,
+) -> Result<Vec<BlockStats>, BlockStatsError> {
+    let fetcher = StatsFetcher::connect(rpc_client).await?;
+    let rpc_methods = &fetcher.source.rpc_methods;
+    let mut hash = rpc_methods.chain_get_finalized_head().await?;
+    let mut stats = Vec::with_capacity(count);
+    while stats.len() < count {
+        let header = rpc_methods
+            .chain_get_header(Some(hash))
+            .await?
+            .ok_or(BlockStatsError::BlockNotAvailable(hash))?;
+        stats.push(fetcher.fetch(hash, &header).await?);
+        if header.number == 0 {
+            break;
+        }
+        hash = header.parent_hash;
+    }
+    stats.reverse();
+    Ok(stats)
+}
+
+/// Everything needed to compute the stats of a block.
+#[derive(Clone)]
+pub(crate) struct StatsFetcher<S = SubxtSource> {
+    source: S,
+    relay_client: Option<RpcClient>,
+    strict_limits: bool,
+    strict_stats: bool,
+    track_finality: bool,
+    soft_pov_budget: Option<Percent>,
+    #[cfg(feature = "serde")]
+    capture: Option<capture::CaptureWriter>,
+    /// The limits of every runtime version encountered so far by `spec_version`.
+    limits: Arc<Mutex<HashMap<u32, Limits>>>,
+}
+
+/// A block of a range with its used weight if it could be queried for the whole range.
+type ResolvedBlock = (H256, Header, Option<PerDispatchClass<Weight>>);
+
+/// What the blocks built by a runtime version are compared against.
+#[derive(Clone, Copy)]
+struct Limits {
+    block_weights: BlockWeights,
+    max_pov: Bytes,
+}
+
+impl<S: StatsSource> StatsFetcher<S> {
+    pub(crate) fn new(source: S) -> Self {
+        Self {
+            source,
+            relay_client: None,
+            strict_limits: false,
+            strict_stats: false,
+            track_finality: false,
+            soft_pov_budget: None,
+            #[cfg(feature = "serde")]
+            capture: None,
+            limits: Arc::default(),
+        }
+    }
+
+    /// Read [`BlockStats::max_pov`] from the relay chain `relay_client` is connected to.
+    pub(crate) fn relay_client(mut self, relay_client: RpcClient) -> Self {
+        self.relay_client = Some(relay_client);
+        self
+    }
+
+    /// Fail on blocks whose PoV is larger than [`BlockStats::max_pov`].
+    pub(crate) fn strict_limits(mut self, strict: bool) -> Self {
+        self.strict_limits = strict;
+        self
+    }
+
+    /// Fail on blocks whose size stats are obviously wrong instead of logging a warning.
+    pub(crate) fn strict_stats(mut self, strict: bool) -> Self {
+        self.strict_stats = strict;
+        self
+    }
+
+    /// Also follow the finalized blocks when subscribing to fill in [`BlockStats::finality_lag`].
+    pub(crate) fn track_finality(mut self, track: bool) -> Self {
+        self.track_finality = track;
+        self
+    }
+
+    /// Fill in [`BlockStats::over_soft_budget`].
+    pub(crate) fn soft_pov_budget(mut self, budget: Option<Percent>) -> Self {
+        self.soft_pov_budget = budget;
+        self
+    }
+
+    /// Write the raw inputs of every block to `capture`.
+    #[cfg(feature = "serde")]
+    pub(crate) fn capture(mut self, capture: capture::CaptureWriter) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// The limits of the runtime `version` that built the block with the given `hash`.
+    ///
+    /// They are read again for every runtime version encountered so that blocks after
+    /// a runtime upgrade aren't compared against stale limits. The relay chain is asked
+    /// again at the same time.
+    async fn limits(
+        &self,
+        hash: H256,
+        header: &Header,
+        version: RuntimeVersion,
+    ) -> Result<Limits, BlockStatsError> {
+        let known = self
+            .limits
+            .lock()
+            .expect("never poisoned")
+            .get(&version.spec_version)
+            .copied();
+        if let Some(limits) = known {
+            return Ok(limits);
+        }
+        let block_weights = self
+            .source
+            .max_block_weights_at(source::runtime_state(hash, header), version)
+            .await?;
+        let max_pov = match &self.relay_client {
+            Some(relay_client) => {
+                let config = fetch_relay_config(relay_client.clone()).await?;
+                Bytes(u64::from(config.max_pov_size) / 2)
+            }
+            None => Bytes(POV_MAX),
+        };
+        if !self.limits.lock().expect("never poisoned").is_empty() {
+            tracing::info!(
+                "Runtime version {} built #{}. Refreshed the limits.",
+                version.spec_version,
+                header.number,
+            );
+        }
+        let limits = Limits {
+            block_weights,
+            max_pov,
+        };
+        self.limits
+            .lock()
+            .expect("never poisoned")
+            .insert(version.spec_version, limits);
+        Ok(limits)
+    }
+
+    async fn fetch(&self, hash: H256, header: &Header) -> Result<BlockStats, BlockStatsError> {
+        self.fetch_with_weight(hash, header, None).await
+    }
+
+    /// Like [`Self::fetch`] but with the used weight already known unless it is `None`.
+    async fn fetch_with_weight(
+        &self,
+        hash: H256,
+        header: &Header,
+        weight: Option<PerDispatchClass<Weight>>,
+    ) -> Result<BlockStats, BlockStatsError> {
+        let started = Instant::now();
+        let stats = self.source.block_stats(hash).await?;
+        if let Some(reason) = stats.inconsistency() {
+            if self.strict_stats {
+                return Err(BlockStatsError::InconsistentStats { hash, reason });
+            }
+            tracing::warn!("Inconsistent stats for block #{}: {reason}.", header.number);
+        }
+        let version = self.source.runtime_version(hash, header).await?;
+        let limits = self.limits(hash, header, version).await?;
+        let weight = match weight {
+            Some(weight) => weight,
+            None => self.source.block_weight_at(hash, header, version).await?,
+        };
+        let extrinsics = self.source.extrinsic_stats(hash).await?;
+        if let Some(encoded_len) = extrinsics.encoded_len.filter(|len| *len != stats.block_len) {
+            tracing::warn!(
+                "Node reported a length of {} bytes for block {hash:?} but it encodes to \
+                 {encoded_len} bytes.",
+                stats.block_len,
+            );
+        }
+        let messages = self.source.message_counts(hash).await?;
+        let para_id = self.source.para_id().await?;
+        let witness_nodes = self.source.witness_node_count(hash).await?;
+        let author = self.source.block_author(hash, header).await?;
+        let pov_len = stats.witness_len + stats.block_len;
+        if self.strict_limits && pov_len > limits.max_pov {
+            return Err(BlockStatsError::PovExceedsMax {
+                observed: pov_len,
+                max: limits.max_pov,
+            });
+        }
+        let total_weight = weight.normal + weight.operational + weight.mandatory;
+
+        let mut block = BlockStats {
+            hash,
+            number: header.number,
+            state_root: header.state_root,
+            extrinsics_root: header.extrinsics_root,
+            spec_version: version.spec_version,
+            transaction_version: version.transaction_version,
+            pov_len,
+            witness_len: stats.witness_len,
+            witness_nodes,
+            len: stats.block_len,
+            weight: total_weight,
+            class_weight: weight,
+            user_weight: extrinsics
+                .overhead_weight
+                .map(|overhead| total_weight.saturating_sub(overhead)),
+            num_extrinsics: stats.num_extrinsics,
+            num_fee_paying: extrinsics.num_fee_paying,
+            num_signed: extrinsics.num_signed,
+            largest_extrinsic_weight: extrinsics.largest_extrinsic_weight,
+            call_weights: extrinsics.call_weights,
+            ump_messages: messages.ump,
+            dmp_messages: messages.dmp,
+            hrmp_messages: messages.hrmp,
+            para_id,
+            author,
+            max_pov: limits.max_pov,
+            over_soft_budget: None,
+            max_weight: limits.block_weights.max_block,
+            class_limits: limits.block_weights.per_class,
+            base_block: limits.block_weights.base_block,
+            fetch_duration: started.elapsed(),
+            lag: Duration::ZERO,
+            received_at: None,
+            finality_lag: None,
+        };
+        block.over_soft_budget = self
+            .soft_pov_budget
+            .map(|budget| block.is_over_soft_budget(budget));
+        // Only after every call succeeded, so that the capture has no blocks that were
+        // never emitted.
+        #[cfg(feature = "serde")]
+        if let Some(capture) = &self.capture {
+            capture.write(&capture::RawCapture {
+                hash,
+                header: header.clone(),
+                stats,
+                weight,
+                version,
+                block_weights: limits.block_weights,
+                max_pov: limits.max_pov,
+            })?;
+        }
+        Ok(block)
+    }
+}
+
+impl StatsFetcher {
+    async fn connect(rpc_client: RpcClient) -> Result<Self, BlockStatsError> {
+        Ok(Self::new(SubxtSource::new(rpc_client).await?))
+    }
+
+    /// Find the hashes, headers and used weights of the blocks in `chunk`.
+    ///
+    /// Uses a single call for the hashes and the weights, instead of one per block. The
+    /// headers are requested concurrently with the weights. The weights are `None` if
+    /// the node doesn't allow querying storage over a range.
+    async fn resolve_chunk(
+        &self,
+        chunk: RangeInclusive<u32>,
+    ) -> Result<Vec<ResolvedBlock>, BlockStatsError> {
+        let (first, last) = chunk.into_inner();
+        // Also the parent of the first block, whose state holds the runtime that built it.
+        let from = first.saturating_sub(1);
+        let mut hashes = self
+            .source
+            .block_hashes(from..=last)
+            .await?
+            .into_iter()
+            .zip(from..)
+            .map(|(hash, number)| hash.ok_or(BlockStatsError::UnknownBlockNumber(number)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The genesis block has no parent.
+        let parent = if first == 0 {
+            hashes[0]
+        } else {
+            hashes.remove(0)
+        };
+        let headers = stream::iter(hashes.iter().copied())
+            .map(|hash| async move {
+                self.source
+                    .rpc_methods
+                    .chain_get_header(Some(hash))
                     .await?
-                    .ok_or_else(|| Error::Other("Block not available.".to_string()))?;
-                let weight = client
-                    .storage()
-                    .at(block.hash())
-                    .fetch_or_default(&block_weight_address)
-                    .await?;
-                let pov_len = stats.witness_len + stats.block_len;
-                let total_weight = weight.normal + weight.operational + weight.mandatory;
-
-                Ok(BlockStats {
-                    hash: block.hash(),
-                    number: block.number(),
-                    pov_len,
-                    witness_len: stats.witness_len,
-                    len: stats.block_len,
-                    weight: total_weight,
-                    num_extrinsics: stats.num_extrinsics,
-                    max_pov: POV_MAX,
-                    max_weight: max_block_weights.max_block,
-                })
+                    .ok_or(BlockStatsError::BlockNotAvailable(hash))
+            })
+            .buffered(HEADER_CONCURRENCY)
+            .try_collect::<Vec<_>>();
+        let (headers, weights) =
+            future::join(headers, self.source.block_weights_of_range(parent, &hashes)).await;
+        let weights = match weights {
+            Ok(weights) => weights.into_iter().map(Some).collect(),
+            Err(err) => {
+                tracing::debug!("Querying weights of #{first} and following failed: {err}");
+                vec![None; hashes.len()]
             }
-        },
-    )))
+        };
+        Ok(hashes
+            .into_iter()
+            .zip(headers?)
+            .zip(weights)
+            .map(|((hash, header), weight)| (hash, header, weight))
+            .collect())
+    }
+
+    /// `None` means the current best block.
+    async fn fetch_by_hash(&self, hash: Option<H256>) -> Result<BlockStats, BlockStatsError> {
+        // `chain_getHeader` defaults to the best block. `at_latest` would be the finalized one.
+        let header = self
+            .source
+            .rpc_methods
+            .chain_get_header(hash)
+            .await?
+            .ok_or_else(|| match hash {
+                Some(hash) => BlockStatsError::BlockNotAvailable(hash),
+                None => BlockStatsError::UnexpectedResponse {
+                    method: "chain_getHeader",
+                    reason: "no best block".into(),
+                },
+            })?;
+        self.fetch(header.hash(), &header).await
+    }
+}
+
+/// A size in bytes.
+///
+/// Used for the sizes of [`BlockStats`] so that they can't be mixed up with other `u64`
+/// values like the components of a [`Weight`]. Serialized as a plain number.
+///
+/// ```
+/// use blockstats::Bytes;
+///
+/// let size = Bytes::from_kib(3) + Bytes(512);
+/// assert_eq!(size, Bytes(3584));
+/// assert_eq!(size.as_kib(), 3.5);
+/// assert_eq!(u64::from(size), 3584);
+/// assert_eq!(size.to_string(), "3584B");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Bytes(pub u64);
+
+impl Bytes {
+    /// `kib` multiples of 1024 bytes.
+    pub const fn from_kib(kib: u64) -> Self {
+        Self(kib.saturating_mul(1024))
+    }
+
+    /// The size in bytes.
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// The size in multiples of 1024 bytes.
+    pub fn as_kib(self) -> f64 {
+        self.0 as f64 / 1024.0
+    }
+
+    /// Subtract `other` stopping at zero.
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Split into `parts` equal parts. `None` if `parts` is zero.
+    pub fn checked_div(self, parts: u64) -> Option<Self> {
+        self.0.checked_div(parts).map(Self)
+    }
+}
+
+impl From<u64> for Bytes {
+    fn from(bytes: u64) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Bytes> for u64 {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl From<Bytes> for u128 {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0.into()
+    }
+}
+
+/// Saturates instead of overflowing like the rest of the crate.
+impl Add for Bytes {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}B", self.0)
+    }
 }
 
 /// Copied from `sp_weight` to additionally implement `scale_decode::DecodeAsType`.
+///
+/// Weights are decoded using the type information of the metadata. Components other
+/// than `ref_time` and `proof_size` are ignored so that decoding keeps working if a
+/// future runtime adds more of them.
+///
+/// # Example
+///
+/// ```
+/// use blockstats::Weight;
+/// use codec::{Compact, Encode};
+/// use scale_info::{PortableRegistry, Registry, TypeInfo};
+/// use subxt::ext::scale_decode::DecodeAsType;
+///
+/// #[derive(Encode, TypeInfo)]
+/// #[codec(crate = codec)]
+/// struct WeightWithExtraDimension {
+///     ref_time: Compact<u64>,
+///     proof_size: Compact<u64>,
+///     storage_deposit: u64,
+/// }
+///
+/// let mut registry = Registry::new();
+/// let ty = registry.register_type(&scale_info::meta_type::<WeightWithExtraDimension>()).id;
+/// let types = PortableRegistry::from(registry);
+/// let encoded = WeightWithExtraDimension {
+///     ref_time: Compact(1_000),
+///     proof_size: Compact(64),
+///     storage_deposit: 7,
+/// }
+/// .encode();
+///
+/// let weight = Weight::decode_as_type(&mut &encoded[..], ty, &types).unwrap();
+/// assert_eq!(weight, Weight::from_parts(1_000, 64));
+/// ```
 #[derive(
     Copy,
     Clone,
@@ -154,46 +1670,148 @@ pub async fn subscribe_stats_with_client(
     scale_decode::DecodeAsType,
 )]
 #[decode_as_type(crate_path = "scale_decode")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Weight {
     #[codec(compact)]
-    /// The weight of computational time used based on some reference hardware.
+    /// The weight of computational time used based on some reference hardware in
+    /// picoseconds.
     ref_time: u64,
     #[codec(compact)]
-    /// The weight of storage space used by proof of validity.
+    /// The weight of storage space used by proof of validity in bytes.
     proof_size: u64,
 }
 
+impl Weight {
+    /// Create a weight from its two components.
+    pub const fn from_parts(ref_time: u64, proof_size: u64) -> Self {
+        Self {
+            ref_time,
+            proof_size,
+        }
+    }
+
+    /// The weight of computational time used based on some reference hardware.
+    ///
+    /// Measured in picoseconds.
+    pub fn ref_time(&self) -> u64 {
+        self.ref_time
+    }
+
+    /// The weight of storage space used by proof of validity.
+    ///
+    /// Measured in bytes.
+    pub fn proof_size(&self) -> u64 {
+        self.proof_size
+    }
+
+    /// Subtract `other` from both components stopping at zero.
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self {
+            ref_time: self.ref_time.saturating_sub(other.ref_time),
+            proof_size: self.proof_size.saturating_sub(other.proof_size),
+        }
+    }
+
+    /// Convert a legacy one dimensional weight which only measured computation.
+    fn from_legacy(ref_time: u64) -> Self {
+        Self {
+            ref_time,
+            proof_size: 0,
+        }
+    }
+}
+
+/// Saturates instead of overflowing like the rest of the crate.
 impl Add for Weight {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
         Self {
-            ref_time: self.ref_time + rhs.ref_time,
-            proof_size: self.proof_size + rhs.proof_size,
+            ref_time: self.ref_time.saturating_add(rhs.ref_time),
+            proof_size: self.proof_size.saturating_add(rhs.proof_size),
         }
     }
 }
 
-#[derive(codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
+/// The weight limits of a block.
+///
+/// Copied from `frame_system::limits` to additionally implement `scale_decode::DecodeAsType`.
+#[derive(Copy, Clone, Debug, codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
 #[decode_as_type(crate_path = "scale_decode")]
-struct BlockWeights {
-    pub base_block: Weight,
-    pub max_block: Weight,
-    pub per_class: PerDispatchClass<WeightsPerClass>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockWeights<W = Weight> {
+    /// Base weight of a block.
+    pub base_block: W,
+    /// Maximal total weight of a block.
+    pub max_block: W,
+    /// The limits of each dispatch class.
+    pub per_class: PerDispatchClass<WeightsPerClass<W>>,
+}
+
+impl<W: Copy> BlockWeights<W> {
+    fn map<U>(self, f: impl Fn(W) -> U + Copy) -> BlockWeights<U> {
+        BlockWeights {
+            base_block: f(self.base_block),
+            max_block: f(self.max_block),
+            per_class: self.per_class.map(|class| class.map(f)),
+        }
+    }
 }
 
-#[derive(codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
+/// A value for each dispatch class.
+///
+/// Copied from `frame_support` to additionally implement `scale_decode::DecodeAsType`.
+#[derive(Copy, Clone, Debug, Default, codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
 #[decode_as_type(crate_path = "scale_decode")]
-struct PerDispatchClass<T> {
-    normal: T,
-    operational: T,
-    mandatory: T,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PerDispatchClass<T> {
+    /// Value for `Normal` extrinsics.
+    pub normal: T,
+    /// Value for `Operational` extrinsics.
+    pub operational: T,
+    /// Value for `Mandatory` extrinsics.
+    pub mandatory: T,
 }
 
-#[derive(codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
+impl<T> PerDispatchClass<T> {
+    fn map<U>(self, f: impl Fn(T) -> U) -> PerDispatchClass<U> {
+        PerDispatchClass {
+            normal: f(self.normal),
+            operational: f(self.operational),
+            mandatory: f(self.mandatory),
+        }
+    }
+}
+
+/// The weight limits of a single dispatch class.
+///
+/// Copied from `frame_system::limits` to additionally implement `scale_decode::DecodeAsType`.
+#[derive(Copy, Clone, Debug, Default, codec::Decode, codec::Encode, scale_decode::DecodeAsType)]
 #[decode_as_type(crate_path = "scale_decode")]
-struct WeightsPerClass {
-    pub base_extrinsic: Weight,
-    pub max_extrinsic: Option<Weight>,
-    pub max_total: Option<Weight>,
-    pub reserved: Option<Weight>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightsPerClass<W = Weight> {
+    /// Base weight of a single extrinsic of this class.
+    pub base_extrinsic: W,
+    /// Maximal weight of a single extrinsic of this class.
+    ///
+    /// `None` means there is no limit apart from the limit of the whole class.
+    pub max_extrinsic: Option<W>,
+    /// Maximal weight of all extrinsics of this class in a block.
+    ///
+    /// `None` means the class is only limited by `max_block`.
+    pub max_total: Option<W>,
+    /// Weight reserved for this class which can't be used by other classes.
+    ///
+    /// `None` means the class can use all the weight up to `max_block`.
+    pub reserved: Option<W>,
+}
+
+impl<W> WeightsPerClass<W> {
+    fn map<U>(self, f: impl Fn(W) -> U) -> WeightsPerClass<U> {
+        WeightsPerClass {
+            base_extrinsic: f(self.base_extrinsic),
+            max_extrinsic: self.max_extrinsic.map(&f),
+            max_total: self.max_total.map(&f),
+            reserved: self.reserved.map(&f),
+        }
+    }
 }
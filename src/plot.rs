@@ -0,0 +1,100 @@
+//! Render the fill levels of a session as a PNG line chart.
+
+use crate::BlockStats;
+use plotters::prelude::*;
+use std::path::PathBuf;
+
+/// Width and height of the chart in pixels.
+const SIZE: (u32, u32) = (1280, 720);
+
+/// Drawing the chart failed.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to draw the chart: {0}")]
+pub struct PlotError(String);
+
+/// Collects the fill levels of blocks and draws them once finished.
+///
+/// Only the block number and three percentages are kept per block so that long
+/// sessions don't take up much memory. It still grows by 32 bytes per block, as every
+/// block becomes a point of the chart.
+pub struct PlotSink {
+    path: PathBuf,
+    /// Block number and the PoV size, `ref_time` and `proof_size` percentages.
+    points: Vec<(u32, [f64; 3])>,
+}
+
+impl PlotSink {
+    /// The chart will be written to `path` when calling [`PlotSink::finish`].
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            points: Vec::new(),
+        }
+    }
+
+    /// Add a block to the chart.
+    pub fn push(&mut self, stats: &BlockStats) {
+        self.points.push((
+            stats.number,
+            [
+                stats.pov_percent().as_percent(),
+                stats.ref_time_percent().as_percent(),
+                stats.proof_size_percent().as_percent(),
+            ],
+        ));
+    }
+
+    /// Draw the fill level of every resource against the block number.
+    ///
+    /// Creates (or truncates) the file. Nothing is written if no block was pushed.
+    pub fn finish(self) -> Result<(), PlotError> {
+        self.draw().map_err(|err| PlotError(err.to_string()))
+    }
+
+    fn draw(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return Ok(());
+        };
+        let numbers = first.0.min(last.0)..first.0.max(last.0).max(first.0 + 1);
+        // Overweight blocks can exceed 100%.
+        let max_percent = self
+            .points
+            .iter()
+            .flat_map(|(_, percents)| *percents)
+            .fold(100.0, f64::max);
+
+        let root = BitMapBackend::new(&self.path, SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption("Block fill level", ("sans-serif", 24))
+            .margin(16)
+            .x_label_area_size(40)
+            .y_label_area_size(56)
+            .build_cartesian_2d(numbers, 0.0..max_percent)?;
+        chart
+            .configure_mesh()
+            .x_desc("Block number")
+            .y_desc("Percent of limit")
+            .draw()?;
+
+        let series = [("PoV Size", RED), ("RefTime", BLUE), ("ProofSize", GREEN)];
+        for (index, (label, color)) in series.into_iter().enumerate() {
+            chart
+                .draw_series(LineSeries::new(
+                    self.points
+                        .iter()
+                        .map(|(number, percents)| (*number, percents[index])),
+                    color,
+                ))?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 16, y)], color));
+        }
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! Information about the relay chain the parachain is connected to.
+
+use crate::BlockStatsError;
+use std::fmt;
+use subxt::{
+    backend::rpc::RpcClient,
+    ext::scale_decode,
+    storage::{address::StaticStorageMapKey, address::Yes, Address},
+    OnlineClient, PolkadotConfig,
+};
+
+/// The parts of the relay chain's `Configuration::ActiveConfig` that limit parachains.
+///
+/// Returned by [`fetch_relay_config`]. Only the fields listed here are decoded which keeps
+/// this working across changes of the full `HostConfiguration` type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+pub struct RelayConfig {
+    /// The maximum size of a PoV in bytes.
+    pub max_pov_size: u32,
+    /// The maximum size of a parachain's head data in bytes.
+    pub max_head_data_size: u32,
+    /// The maximum size of a parachain's validation code in bytes.
+    pub max_code_size: u32,
+    /// Number of relay chain blocks a parachain needs to wait between two code upgrades.
+    pub validation_upgrade_cooldown: u32,
+    /// Number of relay chain blocks after which a scheduled code upgrade is applied.
+    pub validation_upgrade_delay: u32,
+}
+
+impl fmt::Display for RelayConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Relay: MaxPoV={}KiB MaxHeadData={}KiB MaxCode={}KiB UpgradeCooldown={} UpgradeDelay={}",
+            self.max_pov_size / 1024,
+            self.max_head_data_size / 1024,
+            self.max_code_size / 1024,
+            self.validation_upgrade_cooldown,
+            self.validation_upgrade_delay,
+        )
+    }
+}
+
+/// Read the active host configuration of the relay chain node `relay_client` is connected to.
+///
+/// Uses the configuration as of the current best block.
+pub async fn fetch_relay_config(relay_client: RpcClient) -> Result<RelayConfig, BlockStatsError> {
+    let client = OnlineClient::<PolkadotConfig>::from_rpc_client(relay_client).await?;
+    let address: Address<StaticStorageMapKey, RelayConfig, Yes, Yes, ()> =
+        Address::new("Configuration", "ActiveConfig", Vec::new());
+    Ok(client
+        .storage()
+        .at_latest()
+        .await?
+        .fetch_or_default(&address)
+        .await?)
+}
@@ -0,0 +1,1490 @@
+//! Where the data needed to compute [`BlockStats`](crate::BlockStats) comes from.
+
+use crate::{BlockStatsError, BlockWeights, Bytes, PerDispatchClass, Weight};
+use codec::{Compact, Decode, Encode};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt, TryStreamExt};
+use scale_info::{PortableRegistry, TypeDef, TypeDefPrimitive};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+use subxt::{
+    backend::{
+        legacy::LegacyRpcMethods,
+        rpc::{rpc_params, RpcClient},
+    },
+    config::substrate::DigestItem,
+    error::MetadataError,
+    events::{EventDetails, Phase, StaticEvent},
+    ext::{
+        scale_decode::{self, DecodeAsType},
+        sp_core::H256,
+    },
+    metadata::types::StorageEntryType,
+    storage::{address::StaticStorageMapKey, address::Yes, Address},
+    utils::{AccountId32, Encoded, Static},
+    Config, Metadata, OnlineClient, PolkadotConfig as DefaultConfig,
+};
+
+/// The header of a block.
+pub type Header = <DefaultConfig as Config>::Header;
+
+/// Hash and header of each new block as returned by [`StatsSource::subscribe_blocks`].
+pub type BlockStream = BoxStream<'static, Result<(H256, Header), BlockStatsError>>;
+
+/// Size related stats of a block as reported by the node's `dev_getBlockStats` RPC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawBlockStats {
+    /// Size of the storage proof.
+    pub witness_len: Bytes,
+    /// Size of the block.
+    pub block_len: Bytes,
+    /// Number of extrinsics in the block.
+    pub num_extrinsics: u64,
+}
+
+/// Number of cross-chain messages of a parachain block.
+///
+/// Each count is `None` if the runtime doesn't expose it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    /// Upward messages sent to the relay chain.
+    pub ump: Option<u64>,
+    /// Downward messages received from the relay chain and processed.
+    pub dmp: Option<u64>,
+    /// Outbound horizontal messages sent to other parachains.
+    pub hrmp: Option<u64>,
+}
+
+/// The weight an extrinsic used as reported by its dispatch info.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallWeight {
+    /// The pallet of the called function.
+    pub pallet: String,
+    /// The name of the called function.
+    pub call: String,
+    /// The actual weight used by the call.
+    pub weight: Weight,
+}
+
+/// What a source derives from the body and the events of a block.
+///
+/// Returned by [`StatsSource::extrinsic_stats`]. Each value is `None` if the source
+/// doesn't provide it.
+#[derive(Clone, Debug, Default)]
+pub struct ExtrinsicStats {
+    /// See [`StatsSource::num_fee_paying`].
+    pub num_fee_paying: Option<u64>,
+    /// See [`StatsSource::num_signed`].
+    pub num_signed: Option<u64>,
+    /// See [`StatsSource::largest_extrinsic_weight`].
+    pub largest_extrinsic_weight: Option<PerDispatchClass<Weight>>,
+    /// See [`StatsSource::call_weights`].
+    pub call_weights: Option<Vec<CallWeight>>,
+    /// See [`StatsSource::overhead_weight`].
+    pub overhead_weight: Option<Weight>,
+    /// The length of the SCALE encoded block.
+    ///
+    /// A mismatch with [`RawBlockStats::block_len`] is logged as a warning as it points
+    /// at an accounting bug in the node.
+    pub encoded_len: Option<Bytes>,
+}
+
+impl RawBlockStats {
+    /// Why these stats can't be those of a real block, if they obviously can't.
+    ///
+    /// Every block has a header, so its length is never zero. And every extrinsic, even
+    /// the timestamp inherent, touches storage which ends up in the storage proof.
+    pub fn inconsistency(&self) -> Option<String> {
+        if self.block_len == Bytes(0) {
+            return Some(format!(
+                "block_len is zero with {} extrinsics",
+                self.num_extrinsics
+            ));
+        }
+        if self.witness_len == Bytes(0) && self.num_extrinsics > 0 {
+            return Some(format!(
+                "witness_len is zero with {} extrinsics",
+                self.num_extrinsics
+            ));
+        }
+        None
+    }
+}
+
+/// The version of the runtime that produced a block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuntimeVersion {
+    /// Incremented with every runtime upgrade.
+    pub spec_version: u32,
+    /// Incremented when the encoding of existing extrinsics changes.
+    pub transaction_version: u32,
+}
+
+/// Provides the raw data from which [`BlockStats`](crate::BlockStats) are computed.
+///
+/// [`SubxtSource`] is the implementation talking to a node. Implement this trait to
+/// feed the crate from somewhere else, like a local database or a test double, and
+/// pass it to [`subscribe_stats_with_source`](crate::subscribe_stats_with_source).
+pub trait StatsSource: Clone + Send + Sync + 'static {
+    /// The size related stats of the block with the given `hash`.
+    fn block_stats(&self, hash: H256) -> BoxFuture<'_, Result<RawBlockStats, BlockStatsError>>;
+
+    /// The weight used by the block with the given `hash` for each dispatch class.
+    fn block_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<PerDispatchClass<Weight>, BlockStatsError>>;
+
+    /// Like [`Self::block_weight`] for a block built by the runtime `version`.
+    ///
+    /// This is what the stats are computed from. It is called after
+    /// [`Self::max_block_weights_at`] for the same `version`, so that a source can decode
+    /// the weight with the types of the runtime that wrote it. Otherwise backfilling
+    /// across a runtime upgrade that changed the layout of the weight fails. Defaults to
+    /// [`Self::block_weight`].
+    fn block_weight_at<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'a, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        let _ = (header, version);
+        self.block_weight(hash)
+    }
+
+    /// The version of the runtime that built the block with the given `hash`.
+    ///
+    /// That is the runtime in the state of the parent of the block. A block enacting a
+    /// runtime upgrade already stores the new code but was built by the old one.
+    /// Defaults to [`RuntimeVersion::default`] for sources that don't know the version.
+    fn runtime_version<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<RuntimeVersion, BlockStatsError>> {
+        let _ = (hash, header);
+        futures::future::ready(Ok(RuntimeVersion::default())).boxed()
+    }
+
+    /// How many extrinsics of the block with the given `hash` paid a transaction fee.
+    ///
+    /// `None` if the source doesn't decode events. This is the default.
+    fn num_fee_paying(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// How many extrinsics of the block with the given `hash` are signed.
+    ///
+    /// `None` if the source doesn't count them. This is the default.
+    fn num_signed(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The cross-chain messages of the block with the given `hash`.
+    ///
+    /// All counts are `None` if the source doesn't count them. This is the default.
+    fn message_counts(&self, hash: H256) -> BoxFuture<'_, Result<MessageCounts, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(MessageCounts::default())).boxed()
+    }
+
+    /// Number of trie nodes in the storage proof of the block with the given `hash`.
+    ///
+    /// `None` if the source can't count them. This is the default. `dev_getBlockStats`
+    /// only reports the size of the proof, so [`SubxtSource`] always returns `None`.
+    fn witness_node_count(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<u64>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The ID of the parachain as stored in `ParachainInfo::ParachainId`.
+    ///
+    /// `None` on relay and solo chains or if the source doesn't read it. This is the
+    /// default.
+    fn para_id(&self) -> BoxFuture<'_, Result<Option<u32>, BlockStatsError>> {
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The weight of the heaviest extrinsic of each class in the block with the given `hash`.
+    ///
+    /// Both weight components are maximized independently. `None` if the source doesn't
+    /// decode events. This is the default.
+    fn largest_extrinsic_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<PerDispatchClass<Weight>>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The weight of every extrinsic in the block with the given `hash` in block order.
+    ///
+    /// `None` if the source doesn't decode events. This is the default.
+    fn call_weights(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<Vec<CallWeight>>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The account of the authority that produced the block with the given `hash`.
+    ///
+    /// `None` if the source doesn't decode authors or the consensus isn't supported.
+    /// This is the default.
+    fn block_author<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<Option<AccountId32>, BlockStatsError>> {
+        let _ = (hash, header);
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// The weight used by extrinsics the source considers system overhead.
+    ///
+    /// `None` if the source doesn't distinguish them. This is the default.
+    fn overhead_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<Weight>, BlockStatsError>> {
+        let _ = hash;
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    /// Everything derived from the body and the events of the block with the given `hash`.
+    ///
+    /// The stats are computed from this instead of the individual methods. Defaults to
+    /// calling [`Self::num_fee_paying`], [`Self::num_signed`],
+    /// [`Self::largest_extrinsic_weight`], [`Self::call_weights`] and
+    /// [`Self::overhead_weight`] one after another. Override it to download the body and
+    /// the events of a block only once.
+    fn extrinsic_stats(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<ExtrinsicStats, BlockStatsError>> {
+        async move {
+            Ok(ExtrinsicStats {
+                num_fee_paying: self.num_fee_paying(hash).await?,
+                num_signed: self.num_signed(hash).await?,
+                largest_extrinsic_weight: self.largest_extrinsic_weight(hash).await?,
+                call_weights: self.call_weights(hash).await?,
+                overhead_weight: self.overhead_weight(hash).await?,
+                encoded_len: None,
+            })
+        }
+        .boxed()
+    }
+
+    /// The weight limits of the runtime.
+    fn max_block_weights(&self) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>>;
+
+    /// The weight limits of the runtime `version` stored in the state of the block with the given `hash`.
+    ///
+    /// Called once for every runtime version encountered so that the limits stay correct
+    /// across runtime upgrades. `hash` is the parent of the block built by `version`,
+    /// see [`Self::runtime_version`]. Defaults to [`Self::max_block_weights`].
+    fn max_block_weights_at(
+        &self,
+        hash: H256,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        let _ = (hash, version);
+        self.max_block_weights()
+    }
+
+    /// A stream of all new best blocks.
+    fn subscribe_blocks(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>>;
+
+    /// A stream of all newly finalized blocks.
+    ///
+    /// Only needed for [`StatsSubscriptionBuilder::track_finality`](crate::StatsSubscriptionBuilder::track_finality).
+    fn subscribe_finalized(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>> {
+        async {
+            Err(BlockStatsError::Unsupported(
+                "subscribing to finalized blocks",
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// A [`StatsSource`] that queries a node via RPC.
+#[derive(Clone)]
+pub struct SubxtSource {
+    rpc_client: RpcClient,
+    pub(crate) client: OnlineClient<DefaultConfig>,
+    pub(crate) rpc_methods: LegacyRpcMethods<DefaultConfig>,
+    block_weights: BlockWeights,
+    weight_version: WeightVersion,
+    block_weight_storage: StorageLocation,
+    decode_events: bool,
+    decode_author: bool,
+    count_signed: bool,
+    count_messages: bool,
+    verify_block_len: bool,
+    overhead_pallets: HashSet<String>,
+    metadata_version: u32,
+    para_id: Option<u32>,
+    /// The metadata of every runtime encountered so far by `spec_version`.
+    metadata: RuntimeMetadata,
+}
+
+/// The metadata of every runtime encountered so far by `spec_version`.
+///
+/// Shared by all clones of a source so that it is downloaded only once per runtime.
+#[derive(Clone, Default)]
+pub(crate) struct RuntimeMetadata(Arc<Mutex<HashMap<u32, Metadata>>>);
+
+impl RuntimeMetadata {
+    /// Start with the metadata of the runtime `spec_version` that is already known.
+    pub(crate) fn new(spec_version: u32, metadata: Metadata) -> Self {
+        Self(Arc::new(Mutex::new(HashMap::from([(
+            spec_version,
+            metadata,
+        )]))))
+    }
+
+    /// The metadata of the runtime `spec_version`, downloaded with `download` if unknown.
+    pub(crate) async fn get<F>(
+        &self,
+        spec_version: u32,
+        download: impl FnOnce() -> F,
+    ) -> Result<Metadata, BlockStatsError>
+    where
+        F: std::future::Future<Output = Result<Metadata, BlockStatsError>>,
+    {
+        let known = self
+            .0
+            .lock()
+            .expect("never poisoned")
+            .get(&spec_version)
+            .cloned();
+        if let Some(metadata) = known {
+            return Ok(metadata);
+        }
+        let metadata = download().await?;
+        self.0
+            .lock()
+            .expect("never poisoned")
+            .insert(spec_version, metadata.clone());
+        Ok(metadata)
+    }
+}
+
+/// The location of a storage item.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageLocation {
+    /// Name of the pallet as it appears in the metadata.
+    pub pallet: String,
+    /// Name of the storage item within the pallet.
+    pub item: String,
+    /// The SCALE encoded keys in case the item is a map.
+    ///
+    /// They are hashed as declared in the metadata.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl StorageLocation {
+    /// A storage item that is not a map.
+    pub fn plain(pallet: impl Into<String>, item: impl Into<String>) -> Self {
+        Self {
+            pallet: pallet.into(),
+            item: item.into(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Default for StorageLocation {
+    /// `System::BlockWeight` as found in every runtime using an unmodified `frame_system`.
+    fn default() -> Self {
+        Self::plain("System", "BlockWeight")
+    }
+}
+
+impl SubxtSource {
+    /// Create a source from a connected RPC client.
+    ///
+    /// This already fetches the metadata of the node.
+    pub async fn new(rpc_client: RpcClient) -> Result<Self, BlockStatsError> {
+        let client = OnlineClient::<DefaultConfig>::from_rpc_client(rpc_client.clone()).await?;
+        let (block_weights, weight_version) = max_block_weights(&client.metadata())?;
+        let metadata =
+            RuntimeMetadata::new(client.runtime_version().spec_version, client.metadata());
+        let rpc_methods = LegacyRpcMethods::new(rpc_client.clone());
+        let metadata_version = metadata_version(&rpc_methods).await;
+        tracing::info!("Using metadata V{metadata_version}.");
+        let para_id = para_id(&client).await?;
+        Ok(Self {
+            client,
+            rpc_methods,
+            rpc_client,
+            block_weights,
+            weight_version,
+            block_weight_storage: StorageLocation::default(),
+            decode_events: false,
+            decode_author: false,
+            count_signed: false,
+            count_messages: false,
+            verify_block_len: false,
+            overhead_pallets: HashSet::new(),
+            metadata_version,
+            para_id,
+            metadata,
+        })
+    }
+
+    /// The version of the metadata that was received from the node, e.g. 14 for V14.
+    ///
+    /// Useful when reporting decode errors as these often come down to differences between
+    /// metadata versions.
+    pub fn metadata_version(&self) -> u32 {
+        self.metadata_version
+    }
+
+    /// The ID of the parachain read when connecting.
+    ///
+    /// `None` if the chain has no `ParachainInfo` pallet, e.g. on relay and solo chains.
+    pub fn para_id(&self) -> Option<u32> {
+        self.para_id
+    }
+
+    /// Read the used weight from a different storage item than `System::BlockWeight`.
+    ///
+    /// The item needs to have the same type as `System::BlockWeight`. This is for
+    /// runtimes that customized the storage layout of `frame_system`.
+    pub fn block_weight_storage(mut self, location: StorageLocation) -> Self {
+        self.block_weight_storage = location;
+        self
+    }
+
+    /// Additionally download and decode the events of every block.
+    ///
+    /// This is required for [`StatsSource::num_fee_paying`],
+    /// [`StatsSource::largest_extrinsic_weight`] and [`StatsSource::call_weights`]. It
+    /// costs downloading the events and, for the names of the calls, the body of every
+    /// block. [`StatsSource::extrinsic_stats`] downloads each of them only once per block
+    /// no matter how many of this, [`Self::count_signed`], [`Self::verify_block_len`] and
+    /// [`Self::system_overhead_pallets`] are enabled.
+    pub fn decode_events(mut self, decode: bool) -> Self {
+        self.decode_events = decode;
+        self
+    }
+
+    /// Additionally find out which authority produced every block.
+    ///
+    /// This is required for [`StatsSource::block_author`] and costs an additional storage
+    /// read per block. Supported are chains using Aura, like most parachains, and BABE.
+    /// The author is identified by its consensus key and not by the account that might
+    /// have registered as collator.
+    pub fn decode_author(mut self, decode: bool) -> Self {
+        self.decode_author = decode;
+        self
+    }
+
+    /// Additionally count the signed extrinsics of every block.
+    ///
+    /// This is required for [`StatsSource::num_signed`] and costs downloading the block
+    /// header and body. They are shared with the other options, see [`Self::decode_events`].
+    pub fn count_signed(mut self, count: bool) -> Self {
+        self.count_signed = count;
+        self
+    }
+
+    /// Additionally count the cross-chain messages of every block.
+    ///
+    /// This is required for [`StatsSource::message_counts`] and costs three storage reads
+    /// per block. The counts are read from the `ParachainSystem` pallet. Counts that the
+    /// pallet doesn't store are `None`, as are all counts on chains without it.
+    pub fn count_messages(mut self, count: bool) -> Self {
+        self.count_messages = count;
+        self
+    }
+
+    /// Cross-check the block length reported by `dev_getBlockStats` for every block.
+    ///
+    /// The block is downloaded and its SCALE encoded length compared to the reported one.
+    /// A mismatch is logged as a warning as it points at an accounting bug in the node.
+    /// This costs downloading the header and body of every block, shared with the other
+    /// options, see [`Self::decode_events`].
+    pub fn verify_block_len(mut self, verify: bool) -> Self {
+        self.verify_block_len = verify;
+        self
+    }
+
+    /// Consider all extrinsics calling into these pallets as system overhead.
+    ///
+    /// Their weight is reported by [`StatsSource::overhead_weight`]. It is determined
+    /// from the dispatch info in the events and costs downloading the block body and
+    /// events, shared with the other options, see [`Self::decode_events`]. Weight used by
+    /// hooks like `on_initialize` is never counted as overhead.
+    pub fn system_overhead_pallets<P: Into<String>>(
+        mut self,
+        pallets: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.overhead_pallets = pallets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Check that the storage item the used weight is read from exists and can be decoded.
+    ///
+    /// Without this a missing or incompatible item is only noticed when the first block
+    /// is fetched. `System::BlockWeights` is always checked when creating the source.
+    pub fn validate_metadata(&self) -> Result<(), BlockStatsError> {
+        let location = &self.block_weight_storage;
+        let incompatible = |reason: String| BlockStatsError::IncompatibleStorage {
+            pallet: location.pallet.clone(),
+            item: location.item.clone(),
+            reason,
+        };
+        let metadata = self.client.metadata();
+        let entry = metadata
+            .pallet_by_name(&location.pallet)
+            .and_then(|pallet| pallet.storage())
+            .and_then(|storage| storage.entry_by_name(&location.item))
+            .ok_or_else(|| incompatible("not found in the metadata".into()))?;
+        let expected_keys = match entry.entry_type() {
+            StorageEntryType::Plain(_) => 0,
+            StorageEntryType::Map { hashers, .. } => hashers.len(),
+        };
+        if expected_keys != location.keys.len() {
+            return Err(incompatible(format!(
+                "expects {expected_keys} keys but {} are configured",
+                location.keys.len()
+            )));
+        }
+        // The default value is encoded with the same type as every other value.
+        let value_ty = entry.entry_type().value_ty();
+        let bytes = &mut entry.default_bytes();
+        let decoded = match self.weight_version {
+            WeightVersion::V2 => {
+                PerDispatchClass::<Weight>::decode_as_type(bytes, value_ty, metadata.types())
+                    .map(drop)
+            }
+            WeightVersion::Legacy => {
+                PerDispatchClass::<u64>::decode_as_type(bytes, value_ty, metadata.types()).map(drop)
+            }
+        };
+        decoded.map_err(|err| incompatible(err.to_string()))
+    }
+
+    /// The hashes of the blocks with the given numbers with a single call.
+    pub(crate) async fn block_hashes(
+        &self,
+        numbers: RangeInclusive<u32>,
+    ) -> Result<Vec<Option<H256>>, BlockStatsError> {
+        let numbers: Vec<u32> = numbers.collect();
+        Ok(self
+            .rpc_client
+            .request("chain_getBlockHash", rpc_params![numbers])
+            .await?)
+    }
+
+    /// The used weight of consecutive blocks with a single `state_queryStorage` call.
+    ///
+    /// That call is considered unsafe and hence not available on every node. `parent` is
+    /// the parent of the first block. `System::LastRuntimeUpgrade` is queried along with
+    /// the weights to decode each of them with the metadata of the runtime that wrote it.
+    pub(crate) async fn block_weights_of_range(
+        &self,
+        parent: H256,
+        hashes: &[H256],
+    ) -> Result<Vec<PerDispatchClass<Weight>>, BlockStatsError> {
+        let (Some(first), Some(last)) = (hashes.first(), hashes.last()) else {
+            return Ok(Vec::new());
+        };
+        let weight_key = self
+            .client
+            .storage()
+            .address_bytes(&self.block_weight_address::<Weight>())?;
+        let upgrade_key = self.last_runtime_upgrade_key()?;
+        let keys = std::iter::once(&weight_key[..]).chain(upgrade_key.as_deref());
+        let change_sets = self
+            .rpc_methods
+            .state_query_storage(keys, *first, Some(*last))
+            .await?;
+        // Only blocks where a value changed are contained. The others keep the values
+        // of the block before.
+        let mut changes = change_sets.into_iter().peekable();
+        // Without `LastRuntimeUpgrade` all blocks are assumed to be built by the runtime
+        // we connected to as before.
+        let mut spec_version = self.client.runtime_version().spec_version;
+        let mut current = PerDispatchClass::default();
+        let mut weights = Vec::with_capacity(hashes.len());
+        for (index, hash) in hashes.iter().enumerate() {
+            if let Some(set) = changes.next_if(|set| set.block == *hash) {
+                let changed = |key: &[u8]| {
+                    set.changes
+                        .iter()
+                        .find(|(changed, _)| changed.0 == key)
+                        .map(|(_, value)| value.as_ref())
+                };
+                if let Some(upgrade) = upgrade_key.as_deref().and_then(changed).flatten() {
+                    spec_version = last_runtime_upgrade(&upgrade.0)?;
+                }
+                if let Some(value) = changed(&weight_key) {
+                    current = match value {
+                        Some(value) => {
+                            let state =
+                                index.checked_sub(1).map_or(parent, |parent| hashes[parent]);
+                            let metadata = self.metadata_at(state, spec_version).await?;
+                            decode_block_weight_with(
+                                &metadata,
+                                &self.block_weight_storage,
+                                &value.0,
+                            )?
+                        }
+                        None => PerDispatchClass::default(),
+                    };
+                }
+            }
+            weights.push(current);
+        }
+        Ok(weights)
+    }
+
+    /// The metadata of the runtime `spec_version` which is stored in the state of the
+    /// block with the given `hash`.
+    async fn metadata_at(
+        &self,
+        hash: H256,
+        spec_version: u32,
+    ) -> Result<Metadata, BlockStatsError> {
+        self.metadata
+            .get(spec_version, || async move {
+                Ok(self.rpc_methods.state_get_metadata(Some(hash)).await?)
+            })
+            .await
+    }
+
+    /// The raw key of `System::LastRuntimeUpgrade`. `None` if the runtime doesn't have it.
+    fn last_runtime_upgrade_key(&self) -> Result<Option<Vec<u8>>, BlockStatsError> {
+        let exists = self
+            .client
+            .metadata()
+            .pallet_by_name("System")
+            .and_then(|pallet| pallet.storage())
+            .and_then(|storage| storage.entry_by_name("LastRuntimeUpgrade"))
+            .is_some();
+        if !exists {
+            return Ok(None);
+        }
+        let address: Address<StaticStorageMapKey, (), Yes, Yes, ()> =
+            Address::new("System", "LastRuntimeUpgrade", Vec::new());
+        Ok(Some(self.client.storage().address_bytes(&address)?))
+    }
+
+    /// The value of the `u32` or the length of the vector stored in the `ParachainSystem`
+    /// storage `item` at `hash`.
+    ///
+    /// Only the length prefix of a vector is decoded, so the type of its elements doesn't
+    /// matter. `None` if the item doesn't exist in the metadata.
+    async fn parachain_system_count(
+        &self,
+        hash: H256,
+        item: &str,
+        is_vec: bool,
+    ) -> Result<Option<u64>, BlockStatsError> {
+        let exists = self
+            .client
+            .metadata()
+            .pallet_by_name("ParachainSystem")
+            .and_then(|pallet| pallet.storage())
+            .and_then(|storage| storage.entry_by_name(item))
+            .is_some();
+        if !exists {
+            return Ok(None);
+        }
+        let address: Address<StaticStorageMapKey, (), Yes, Yes, ()> =
+            Address::new("ParachainSystem", item, Vec::new());
+        let key = self.client.storage().address_bytes(&address)?;
+        let Some(value) = self.client.storage().at(hash).fetch_raw(key).await? else {
+            // Both kinds of items default to zero.
+            return Ok(Some(0));
+        };
+        let bytes = &mut &value[..];
+        let count = if is_vec {
+            Compact::<u32>::decode(bytes).map(|len| len.0)
+        } else {
+            u32::decode(bytes)
+        };
+        count
+            .map(|count| Some(count.into()))
+            .map_err(|err| BlockStatsError::IncompatibleStorage {
+                pallet: "ParachainSystem".into(),
+                item: item.into(),
+                reason: err.to_string(),
+            })
+    }
+
+    /// The address of the used weight decoding the weights as `W`.
+    fn block_weight_address<W: scale_decode::IntoVisitor>(
+        &self,
+    ) -> Address<StaticStorageMapKey, PerDispatchClass<W>, Yes, Yes, ()> {
+        block_weight_address(&self.block_weight_storage)
+    }
+}
+
+impl StatsSource for SubxtSource {
+    fn block_stats(&self, hash: H256) -> BoxFuture<'_, Result<RawBlockStats, BlockStatsError>> {
+        async move {
+            let stats = self
+                .rpc_methods
+                .dev_get_block_stats(hash)
+                .await?
+                .ok_or(BlockStatsError::BlockNotAvailable(hash))?;
+            Ok(RawBlockStats {
+                witness_len: Bytes(stats.witness_len),
+                block_len: Bytes(stats.block_len),
+                num_extrinsics: stats.num_extrinsics,
+            })
+        }
+        .boxed()
+    }
+
+    fn block_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        async move {
+            let storage = self.client.storage().at(hash);
+            let weight = match self.weight_version {
+                WeightVersion::V2 => {
+                    storage
+                        .fetch_or_default(&self.block_weight_address())
+                        .await?
+                }
+                WeightVersion::Legacy => storage
+                    .fetch_or_default(&self.block_weight_address::<u64>())
+                    .await?
+                    .map(Weight::from_legacy),
+            };
+            Ok(weight)
+        }
+        .boxed()
+    }
+
+    fn block_weight_at<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'a, Result<PerDispatchClass<Weight>, BlockStatsError>> {
+        async move {
+            if version.spec_version == self.client.runtime_version().spec_version {
+                return self.block_weight(hash).await;
+            }
+            let key = self
+                .client
+                .storage()
+                .address_bytes(&self.block_weight_address::<Weight>())?;
+            let Some(value) = self.client.storage().at(hash).fetch_raw(key).await? else {
+                return Ok(PerDispatchClass::default());
+            };
+            let metadata = self
+                .metadata_at(runtime_state(hash, header), version.spec_version)
+                .await?;
+            decode_block_weight_with(&metadata, &self.block_weight_storage, &value)
+        }
+        .boxed()
+    }
+
+    fn runtime_version<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<RuntimeVersion, BlockStatsError>> {
+        async move {
+            let version = self
+                .rpc_methods
+                .state_get_runtime_version(Some(runtime_state(hash, header)))
+                .await?;
+            Ok(RuntimeVersion {
+                spec_version: version.spec_version,
+                transaction_version: version.transaction_version,
+            })
+        }
+        .boxed()
+    }
+
+    fn num_fee_paying(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, BlockStatsError>> {
+        async move {
+            if !self.decode_events {
+                return Ok(None);
+            }
+            Ok(Some(count_fee_paying(
+                &self.client.events().at(hash).await?,
+            )?))
+        }
+        .boxed()
+    }
+
+    fn num_signed(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, BlockStatsError>> {
+        async move {
+            if !self.count_signed {
+                return Ok(None);
+            }
+            let block = self.client.blocks().at(hash).await?;
+            Ok(Some(count_signed(&block.extrinsics().await?)?))
+        }
+        .boxed()
+    }
+
+    fn para_id(&self) -> BoxFuture<'_, Result<Option<u32>, BlockStatsError>> {
+        futures::future::ready(Ok(self.para_id)).boxed()
+    }
+
+    fn message_counts(&self, hash: H256) -> BoxFuture<'_, Result<MessageCounts, BlockStatsError>> {
+        async move {
+            if !self.count_messages {
+                return Ok(MessageCounts::default());
+            }
+            Ok(MessageCounts {
+                ump: self
+                    .parachain_system_count(hash, "UpwardMessages", true)
+                    .await?,
+                dmp: self
+                    .parachain_system_count(hash, "ProcessedDownwardMessages", false)
+                    .await?,
+                hrmp: self
+                    .parachain_system_count(hash, "HrmpOutboundMessages", true)
+                    .await?,
+            })
+        }
+        .boxed()
+    }
+
+    fn largest_extrinsic_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<PerDispatchClass<Weight>>, BlockStatsError>> {
+        async move {
+            if !self.decode_events {
+                return Ok(None);
+            }
+            let events = self.client.events().at(hash).await?;
+            Ok(Some(largest_extrinsic_weight(
+                self.weight_version,
+                &events,
+            )?))
+        }
+        .boxed()
+    }
+
+    fn block_author<'a>(
+        &'a self,
+        hash: H256,
+        header: &'a Header,
+    ) -> BoxFuture<'a, Result<Option<AccountId32>, BlockStatsError>> {
+        async move {
+            if !self.decode_author {
+                return Ok(None);
+            }
+            let storage = self.client.storage().at(hash);
+            for log in &header.digest.logs {
+                let DigestItem::PreRuntime(engine, data) = log else {
+                    continue;
+                };
+                match engine {
+                    b"aura" => {
+                        let Ok(slot) = u64::decode(&mut &data[..]) else {
+                            continue;
+                        };
+                        let address: Address<StaticStorageMapKey, Vec<AccountId32>, Yes, Yes, ()> =
+                            Address::new("Aura", "Authorities", Vec::new());
+                        let authorities = storage.fetch_or_default(&address).await?;
+                        return Ok(slot
+                            .checked_rem(authorities.len() as u64)
+                            .and_then(|index| authorities.get(index as usize).cloned()));
+                    }
+                    b"BABE" => {
+                        // All variants of `PreDigest` start with the authority index.
+                        let Ok((_variant, index)) = <(u8, u32)>::decode(&mut &data[..]) else {
+                            continue;
+                        };
+                        let address: Address<
+                            StaticStorageMapKey,
+                            Vec<(AccountId32, u64)>,
+                            Yes,
+                            Yes,
+                            (),
+                        > = Address::new("Babe", "Authorities", Vec::new());
+                        let authorities = storage.fetch_or_default(&address).await?;
+                        return Ok(authorities
+                            .get(index as usize)
+                            .map(|(author, _)| author.clone()));
+                    }
+                    _ => continue,
+                }
+            }
+            Ok(None)
+        }
+        .boxed()
+    }
+
+    fn call_weights(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<Vec<CallWeight>>, BlockStatsError>> {
+        async move {
+            if !self.decode_events {
+                return Ok(None);
+            }
+            let block = self.client.blocks().at(hash).await?;
+            let extrinsics = block.extrinsics().await?;
+            let events = block.events().await?;
+            Ok(Some(call_weights(
+                self.weight_version,
+                &extrinsics,
+                &events,
+            )?))
+        }
+        .boxed()
+    }
+
+    fn overhead_weight(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<Option<Weight>, BlockStatsError>> {
+        async move {
+            if self.overhead_pallets.is_empty() {
+                return Ok(None);
+            }
+            let block = self.client.blocks().at(hash).await?;
+            let extrinsics = block.extrinsics().await?;
+            let events = block.events().await?;
+            Ok(Some(overhead_weight(
+                self.weight_version,
+                &self.overhead_pallets,
+                &extrinsics,
+                &events,
+            )?))
+        }
+        .boxed()
+    }
+
+    fn extrinsic_stats(
+        &self,
+        hash: H256,
+    ) -> BoxFuture<'_, Result<ExtrinsicStats, BlockStatsError>> {
+        async move {
+            let overhead = !self.overhead_pallets.is_empty();
+            let needs_events = self.decode_events || overhead;
+            if !(needs_events || self.count_signed || self.verify_block_len) {
+                return Ok(ExtrinsicStats::default());
+            }
+            // The body and the events are downloaded once and shared by all values.
+            let block = self.client.blocks().at(hash).await?;
+            let extrinsics = block.extrinsics().await?;
+            let events = if needs_events {
+                Some(block.events().await?)
+            } else {
+                None
+            };
+            let decoded = events.as_ref().filter(|_| self.decode_events);
+            Ok(ExtrinsicStats {
+                num_fee_paying: decoded.map(count_fee_paying).transpose()?,
+                num_signed: self
+                    .count_signed
+                    .then(|| count_signed(&extrinsics))
+                    .transpose()?,
+                largest_extrinsic_weight: decoded
+                    .map(|events| largest_extrinsic_weight(self.weight_version, events))
+                    .transpose()?,
+                call_weights: decoded
+                    .map(|events| call_weights(self.weight_version, &extrinsics, events))
+                    .transpose()?,
+                overhead_weight: events
+                    .as_ref()
+                    .filter(|_| overhead)
+                    .map(|events| {
+                        overhead_weight(
+                            self.weight_version,
+                            &self.overhead_pallets,
+                            &extrinsics,
+                            events,
+                        )
+                    })
+                    .transpose()?,
+                encoded_len: self
+                    .verify_block_len
+                    .then(|| encoded_len(block.header(), &extrinsics))
+                    .transpose()?,
+            })
+        }
+        .boxed()
+    }
+
+    fn max_block_weights(&self) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        futures::future::ready(Ok(self.block_weights)).boxed()
+    }
+
+    fn max_block_weights_at(
+        &self,
+        hash: H256,
+        version: RuntimeVersion,
+    ) -> BoxFuture<'_, Result<BlockWeights, BlockStatsError>> {
+        async move {
+            // Avoid decoding the constant again for the runtime we connected to.
+            if version.spec_version == self.client.runtime_version().spec_version {
+                return Ok(self.block_weights);
+            }
+            let metadata = self.metadata_at(hash, version.spec_version).await?;
+            Ok(max_block_weights(&metadata)?.0)
+        }
+        .boxed()
+    }
+
+    fn subscribe_blocks(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>> {
+        async move {
+            let blocks = self.client.blocks().subscribe_best().await?;
+            Ok(blocks
+                .map_ok(|block| (block.hash(), block.header().clone()))
+                .map_err(Into::into)
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn subscribe_finalized(&self) -> BoxFuture<'_, Result<BlockStream, BlockStatsError>> {
+        async move {
+            let blocks = self.client.blocks().subscribe_finalized().await?;
+            Ok(blocks
+                .map_ok(|block| (block.hash(), block.header().clone()))
+                .map_err(Into::into)
+                .boxed())
+        }
+        .boxed()
+    }
+}
+
+/// The decoded body of a block.
+type Extrinsics = subxt::blocks::Extrinsics<DefaultConfig, OnlineClient<DefaultConfig>>;
+
+/// The decoded events of a block.
+type Events = subxt::events::Events<DefaultConfig>;
+
+/// Number of `TransactionPayment::TransactionFeePaid` events.
+fn count_fee_paying(events: &Events) -> Result<u64, BlockStatsError> {
+    let mut count = 0;
+    for event in events.iter() {
+        let event = event?;
+        if event.pallet_name() == "TransactionPayment"
+            && event.variant_name() == "TransactionFeePaid"
+        {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Number of signed extrinsics.
+fn count_signed(extrinsics: &Extrinsics) -> Result<u64, BlockStatsError> {
+    let mut count = 0;
+    for extrinsic in extrinsics.iter() {
+        if extrinsic?.is_signed() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// The weight of the heaviest extrinsic of each class with both components maximized
+/// independently.
+fn largest_extrinsic_weight(
+    version: WeightVersion,
+    events: &Events,
+) -> Result<PerDispatchClass<Weight>, BlockStatsError> {
+    let mut largest = PerDispatchClass::<Weight>::default();
+    for event in events.iter() {
+        let Some(info) = dispatch_info(version, &event?) else {
+            continue;
+        };
+        let class = match info.class {
+            DispatchClass::Normal => &mut largest.normal,
+            DispatchClass::Operational => &mut largest.operational,
+            DispatchClass::Mandatory => &mut largest.mandatory,
+        };
+        *class = Weight::from_parts(
+            class.ref_time().max(info.weight.ref_time()),
+            class.proof_size().max(info.weight.proof_size()),
+        );
+    }
+    Ok(largest)
+}
+
+/// The weight of every extrinsic in block order.
+fn call_weights(
+    version: WeightVersion,
+    extrinsics: &Extrinsics,
+    events: &Events,
+) -> Result<Vec<CallWeight>, BlockStatsError> {
+    let mut calls = BTreeMap::new();
+    for extrinsic in extrinsics.iter() {
+        let extrinsic = extrinsic?;
+        calls.insert(
+            extrinsic.index(),
+            CallWeight {
+                pallet: extrinsic.pallet_name()?.into(),
+                call: extrinsic.variant_name()?.into(),
+                weight: Weight::default(),
+            },
+        );
+    }
+    for event in events.iter() {
+        let event = event?;
+        let Phase::ApplyExtrinsic(index) = event.phase() else {
+            continue;
+        };
+        if let (Some(call), Some(info)) = (calls.get_mut(&index), dispatch_info(version, &event)) {
+            call.weight = info.weight;
+        }
+    }
+    Ok(calls.into_values().collect())
+}
+
+/// The weight used by the extrinsics calling into one of `pallets`.
+fn overhead_weight(
+    version: WeightVersion,
+    pallets: &HashSet<String>,
+    extrinsics: &Extrinsics,
+    events: &Events,
+) -> Result<Weight, BlockStatsError> {
+    let mut overhead = HashSet::new();
+    for extrinsic in extrinsics.iter() {
+        let extrinsic = extrinsic?;
+        if pallets.contains(extrinsic.pallet_name()?) {
+            overhead.insert(extrinsic.index());
+        }
+    }
+    let mut weight = Weight::default();
+    for event in events.iter() {
+        let event = event?;
+        match event.phase() {
+            Phase::ApplyExtrinsic(index) if overhead.contains(&index) => {
+                if let Some(info) = dispatch_info(version, &event) {
+                    weight = weight + info.weight;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(weight)
+}
+
+/// The length of the SCALE encoded block made up of `header` and `extrinsics`.
+fn encoded_len(header: &Header, extrinsics: &Extrinsics) -> Result<Bytes, BlockStatsError> {
+    let mut len = header.encoded_size() + Compact(extrinsics.len() as u32).encoded_size();
+    for extrinsic in extrinsics.iter() {
+        // The body is a vector of length prefixed extrinsics.
+        let extrinsic_len = extrinsic?.bytes().len();
+        len += Compact(extrinsic_len as u32).encoded_size() + extrinsic_len;
+    }
+    Ok(Bytes(len as u64))
+}
+
+/// The block in whose state the runtime that built the block with the given `hash` is stored.
+///
+/// That is its parent, except for the genesis block which has none.
+pub(crate) fn runtime_state(hash: H256, header: &Header) -> H256 {
+    if header.number == 0 {
+        hash
+    } else {
+        header.parent_hash
+    }
+}
+
+/// Read `ParachainInfo::ParachainId` as of the latest block.
+///
+/// The ID can't change without re-registering the parachain so it is only read once.
+async fn para_id(client: &OnlineClient<DefaultConfig>) -> Result<Option<u32>, BlockStatsError> {
+    let (pallet, item) = ("ParachainInfo", "ParachainId");
+    let exists = client
+        .metadata()
+        .pallet_by_name(pallet)
+        .and_then(|pallet| pallet.storage())
+        .and_then(|storage| storage.entry_by_name(item))
+        .is_some();
+    if !exists {
+        return Ok(None);
+    }
+    let address: Address<StaticStorageMapKey, (), Yes, Yes, ()> =
+        Address::new(pallet, item, Vec::new());
+    let key = client.storage().address_bytes(&address)?;
+    let Some(value) = client.storage().at_latest().await?.fetch_raw(key).await? else {
+        return Ok(None);
+    };
+    u32::decode(&mut &value[..])
+        .map(Some)
+        .map_err(|err| BlockStatsError::IncompatibleStorage {
+            pallet: pallet.into(),
+            item: item.into(),
+            reason: err.to_string(),
+        })
+}
+
+/// The newest metadata version subxt requests from the node.
+const LATEST_METADATA_VERSION: u32 = 15;
+
+/// The version of the metadata subxt received when connecting.
+///
+/// subxt asks for [`LATEST_METADATA_VERSION`] and falls back to the legacy call which
+/// always returns V14. Runtimes that don't list their versions only support the latter.
+async fn metadata_version(rpc_methods: &LegacyRpcMethods<DefaultConfig>) -> u32 {
+    let versions = rpc_methods
+        .state_call("Metadata_metadata_versions", None, None)
+        .await
+        .ok()
+        .and_then(|versions| Vec::<u32>::decode(&mut &versions[..]).ok())
+        .unwrap_or_default();
+    if versions.contains(&LATEST_METADATA_VERSION) {
+        LATEST_METADATA_VERSION
+    } else {
+        14
+    }
+}
+
+/// The part of `frame_support::dispatch::DispatchInfo` we are interested in.
+#[derive(scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+struct DispatchInfo<W> {
+    weight: W,
+    class: DispatchClass,
+}
+
+/// `DispatchInfo` of newer runtimes which splits the weight of the call from the weight
+/// of the transaction extensions.
+#[derive(scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+struct SplitDispatchInfo {
+    call_weight: Weight,
+    extension_weight: Weight,
+    class: DispatchClass,
+}
+
+impl From<SplitDispatchInfo> for DispatchInfo<Weight> {
+    fn from(info: SplitDispatchInfo) -> Self {
+        Self {
+            weight: info.call_weight + info.extension_weight,
+            class: info.class,
+        }
+    }
+}
+
+/// Copied from `frame_support::dispatch` to implement `scale_decode::DecodeAsType`.
+#[derive(scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+enum DispatchClass {
+    Normal,
+    Operational,
+    Mandatory,
+}
+
+/// `System::ExtrinsicSuccess` reduced to the dispatch info `I`.
+#[derive(scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+struct ExtrinsicSuccess<I> {
+    dispatch_info: I,
+}
+
+impl<I: scale_decode::IntoVisitor> StaticEvent for ExtrinsicSuccess<I> {
+    const PALLET: &'static str = "System";
+    const EVENT: &'static str = "ExtrinsicSuccess";
+}
+
+/// `System::ExtrinsicFailed` reduced to the dispatch info `I`.
+#[derive(scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "scale_decode")]
+struct ExtrinsicFailed<I> {
+    dispatch_info: I,
+}
+
+impl<I: scale_decode::IntoVisitor> StaticEvent for ExtrinsicFailed<I> {
+    const PALLET: &'static str = "System";
+    const EVENT: &'static str = "ExtrinsicFailed";
+}
+
+/// The dispatch info of an extrinsic if `event` reports its outcome.
+///
+/// Both the layout with a single `weight` and the one with separate `call_weight` and
+/// `extension_weight` are understood. The latter are summed up. A dispatch info that
+/// can't be decoded either way is treated like a missing one instead of failing the
+/// whole block.
+fn dispatch_info(
+    version: WeightVersion,
+    event: &EventDetails<DefaultConfig>,
+) -> Option<DispatchInfo<Weight>> {
+    fn decode<I: scale_decode::IntoVisitor>(
+        event: &EventDetails<DefaultConfig>,
+    ) -> Result<Option<I>, Box<subxt::Error>> {
+        if let Some(success) = event.as_event::<ExtrinsicSuccess<I>>()? {
+            return Ok(Some(success.dispatch_info));
+        }
+        Ok(event
+            .as_event::<ExtrinsicFailed<I>>()?
+            .map(|failed| failed.dispatch_info))
+    }
+    let info = match version {
+        WeightVersion::V2 => decode::<DispatchInfo<Weight>>(event)
+            .or_else(|_| decode::<SplitDispatchInfo>(event).map(|info| info.map(Into::into))),
+        WeightVersion::Legacy => decode::<DispatchInfo<u64>>(event).map(|info| {
+            info.map(|info| DispatchInfo {
+                weight: Weight::from_legacy(info.weight),
+                class: info.class,
+            })
+        }),
+    };
+    info.unwrap_or_else(|err| {
+        tracing::debug!(
+            "Ignoring the dispatch info of event {}::{}: {err}",
+            event.pallet_name(),
+            event.variant_name(),
+        );
+        None
+    })
+}
+
+/// The layout of `Weight` used by a runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WeightVersion {
+    /// A plain `u64` which only measures computational time.
+    Legacy,
+    /// Two components: `ref_time` and `proof_size`.
+    V2,
+}
+
+/// The address of the used weight at `location` decoding the weights as `W`.
+pub(crate) fn block_weight_address<W: scale_decode::IntoVisitor>(
+    location: &StorageLocation,
+) -> Address<StaticStorageMapKey, PerDispatchClass<W>, Yes, Yes, ()> {
+    let keys = location
+        .keys
+        .iter()
+        .map(|key| Static(Encoded(key.clone())))
+        .collect();
+    Address::new(&location.pallet, &location.item, keys)
+}
+
+/// The type of the values of the storage item at `location`.
+pub(crate) fn storage_value_ty(
+    metadata: &Metadata,
+    location: &StorageLocation,
+) -> Result<u32, BlockStatsError> {
+    metadata
+        .pallet_by_name(&location.pallet)
+        .and_then(|pallet| pallet.storage())
+        .and_then(|storage| storage.entry_by_name(&location.item))
+        .map(|entry| entry.entry_type().value_ty())
+        .ok_or_else(|| BlockStatsError::IncompatibleStorage {
+            pallet: location.pallet.clone(),
+            item: location.item.clone(),
+            reason: "not found in the metadata".into(),
+        })
+}
+
+/// Decode a value of `System::BlockWeight` encoded with the given layout.
+///
+/// The type `value_ty` from the metadata is used so that weights with more components
+/// than the known two still decode.
+pub(crate) fn decode_block_weight(
+    version: WeightVersion,
+    mut bytes: &[u8],
+    value_ty: u32,
+    types: &PortableRegistry,
+) -> Result<PerDispatchClass<Weight>, BlockStatsError> {
+    let weight = match version {
+        WeightVersion::V2 => PerDispatchClass::decode_as_type(&mut bytes, value_ty, types),
+        WeightVersion::Legacy => {
+            PerDispatchClass::<u64>::decode_as_type(&mut bytes, value_ty, types)
+                .map(|weight| weight.map(Weight::from_legacy))
+        }
+    };
+    weight.map_err(|err| BlockStatsError::BlockWeightDecode(Box::new(err)))
+}
+
+/// Decode a value of the used weight at `location` with the types of `metadata`.
+pub(crate) fn decode_block_weight_with(
+    metadata: &Metadata,
+    location: &StorageLocation,
+    bytes: &[u8],
+) -> Result<PerDispatchClass<Weight>, BlockStatsError> {
+    let (_, version) = max_block_weights(metadata)?;
+    let value_ty = storage_value_ty(metadata, location)?;
+    decode_block_weight(version, bytes, value_ty, metadata.types())
+}
+
+/// The `spec_version` of an encoded `frame_system::LastRuntimeUpgradeInfo`.
+fn last_runtime_upgrade(mut bytes: &[u8]) -> Result<u32, BlockStatsError> {
+    // The version comes before the name of the spec.
+    Compact::<u32>::decode(&mut bytes)
+        .map(|version| version.0)
+        .map_err(|err| BlockStatsError::IncompatibleStorage {
+            pallet: "System".into(),
+            item: "LastRuntimeUpgrade".into(),
+            reason: err.to_string(),
+        })
+}
+
+/// Decode `System::BlockWeights` and detect which weight layout the runtime uses.
+pub(crate) fn max_block_weights(
+    metadata: &Metadata,
+) -> Result<(BlockWeights, WeightVersion), BlockStatsError> {
+    let pallet_name = "System";
+    let constant_name = "BlockWeights";
+    let pallet = metadata.pallet_by_name_err(pallet_name)?;
+    let constant = pallet
+        .constant_by_name(constant_name)
+        .ok_or_else(|| MetadataError::ConstantNameNotFound(constant_name.to_owned()))?;
+    let version = weight_version(metadata.types(), constant.ty());
+    let decode_err = |source| BlockStatsError::WeightsDecode {
+        pallet: pallet_name,
+        constant: constant_name,
+        source: Box::new(source),
+    };
+    // Decoding with the type from the metadata ignores weight components we don't know.
+    let (bytes, ty, types) = (&mut constant.value(), constant.ty(), metadata.types());
+    let weights = match version {
+        WeightVersion::V2 => BlockWeights::decode_as_type(bytes, ty, types).map_err(decode_err)?,
+        WeightVersion::Legacy => BlockWeights::<u64>::decode_as_type(bytes, ty, types)
+            .map_err(decode_err)?
+            .map(Weight::from_legacy),
+    };
+    Ok((weights, version))
+}
+
+/// Inspect the type of `BlockWeights::max_block` to find out which layout is used.
+///
+/// Anything that isn't a plain `u64` is assumed to be the current two dimensional weight
+/// and will fail during decoding if not.
+fn weight_version(types: &PortableRegistry, block_weights_ty: u32) -> WeightVersion {
+    let max_block_ty = types
+        .resolve(block_weights_ty)
+        .and_then(|ty| match &ty.type_def {
+            TypeDef::Composite(composite) => composite
+                .fields
+                .iter()
+                .find(|field| field.name.as_deref() == Some("max_block"))
+                .map(|field| field.ty.id),
+            _ => None,
+        });
+    match max_block_ty
+        .and_then(|id| types.resolve(id))
+        .map(|ty| &ty.type_def)
+    {
+        Some(TypeDef::Primitive(TypeDefPrimitive::U64)) => WeightVersion::Legacy,
+        _ => WeightVersion::V2,
+    }
+}
@@ -0,0 +1,107 @@
+//! Write stats into a SQLite database for local querying.
+
+use crate::{micros, BlockStats};
+use rusqlite::{params, Connection, Error as SqliteError};
+use std::path::Path;
+
+/// Default number of blocks inserted with a single transaction.
+pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// Buffers blocks and inserts them into the `block_stats` table in batches.
+///
+/// Every batch is written in its own transaction which is much faster than
+/// committing every row. Call [`SqliteSink::finish`] or [`SqliteSink::flush`] to
+/// write the remaining blocks.
+pub struct SqliteSink {
+    connection: Connection,
+    buffer: Vec<BlockStats>,
+    batch_size: usize,
+}
+
+impl SqliteSink {
+    /// Open (or create) the database at `path` and create the table if it doesn't exist.
+    ///
+    /// Every `batch_size` blocks the buffered blocks are inserted.
+    pub fn open(path: impl AsRef<Path>, batch_size: usize) -> Result<Self, SqliteError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS block_stats (
+                hash BLOB NOT NULL,
+                number INTEGER NOT NULL,
+                state_root BLOB NOT NULL,
+                extrinsics_root BLOB NOT NULL,
+                spec_version INTEGER NOT NULL,
+                transaction_version INTEGER NOT NULL,
+                pov_len INTEGER NOT NULL,
+                witness_len INTEGER NOT NULL,
+                len INTEGER NOT NULL,
+                ref_time INTEGER NOT NULL,
+                proof_size INTEGER NOT NULL,
+                num_extrinsics INTEGER NOT NULL,
+                max_pov INTEGER NOT NULL,
+                max_ref_time INTEGER NOT NULL,
+                max_proof_size INTEGER NOT NULL,
+                fetch_duration_us INTEGER NOT NULL,
+                lag_us INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS block_stats_number ON block_stats (number);",
+        )?;
+        Ok(Self {
+            connection,
+            buffer: Vec::with_capacity(batch_size),
+            batch_size: batch_size.max(1),
+        })
+    }
+
+    /// Add a block to the database.
+    pub fn push(&mut self, stats: &BlockStats) -> Result<(), SqliteError> {
+        self.buffer.push(stats.clone());
+        if self.buffer.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Insert all buffered blocks with a single transaction.
+    pub fn flush(&mut self) -> Result<(), SqliteError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO block_stats VALUES
+                    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            )?;
+            for stats in &self.buffer {
+                insert.execute(params![
+                    stats.hash.as_bytes(),
+                    stats.number,
+                    stats.state_root.as_bytes(),
+                    stats.extrinsics_root.as_bytes(),
+                    stats.spec_version,
+                    stats.transaction_version,
+                    stats.pov_len.as_u64(),
+                    stats.witness_len.as_u64(),
+                    stats.len.as_u64(),
+                    stats.weight.ref_time,
+                    stats.weight.proof_size,
+                    stats.num_extrinsics,
+                    stats.max_pov.as_u64(),
+                    stats.max_weight.ref_time,
+                    stats.max_weight.proof_size,
+                    micros(stats.fetch_duration),
+                    micros(stats.lag),
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Insert the remaining blocks and close the database.
+    pub fn finish(mut self) -> Result<(), SqliteError> {
+        self.flush()
+    }
+}
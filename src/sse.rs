@@ -0,0 +1,96 @@
+//! Expose a stats stream to browsers as Server-Sent Events.
+//!
+//! Every block is sent as a `data:` event containing the JSON serialization of
+//! [`BlockStats`]. A browser can consume it with a plain `EventSource`.
+
+use crate::{http::read_request_head, BlockStats, BlockStatsError};
+use futures::{
+    future::{self, Either},
+    StreamExt, TryStream, TryStreamExt,
+};
+use std::{io, net::SocketAddr, pin::pin};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+/// How many blocks a slow client can lag behind before it starts missing blocks.
+const CHANNEL_CAPACITY: usize = 64;
+
+const RESPONSE_HEAD: &[u8] = b"HTTP/1.1 200 OK\r\n\
+    Content-Type: text/event-stream\r\n\
+    Cache-Control: no-cache\r\n\
+    Access-Control-Allow-Origin: *\r\n\
+    \r\n";
+
+/// Serve the blocks of `stats` to every client that connects to `addr`.
+///
+/// The stream is consumed once and fanned out to all connected clients. A client
+/// only receives the blocks that arrive after it connected. Every path serves the
+/// same stream. Errors of the stream are sent as events of type `error`.
+///
+/// Runs until accepting connections fails or `stats` ends. In the latter case the
+/// connections of all clients are closed. Must be called from within a tokio runtime.
+pub async fn serve_sse<S>(stats: S, addr: SocketAddr) -> Result<(), io::Error>
+where
+    S: TryStream<Ok = BlockStats, Error = BlockStatsError> + Send + Unpin + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    // The sender is owned by the forwarding task only so that the clients see it close.
+    let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+    let forward = tokio::spawn(stats.into_stream().for_each(move |stat| {
+        // Sending only fails when no client is connected which is fine.
+        let _ = sender.send(event(stat));
+        future::ready(())
+    }));
+    match future::select(pin!(accept_clients(listener, receiver)), forward).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Ok(()),
+    }
+}
+
+/// Serve every client that connects to `listener` with the events of `receiver`.
+async fn accept_clients(
+    listener: TcpListener,
+    receiver: broadcast::Receiver<String>,
+) -> Result<(), io::Error> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        tokio::spawn(serve_client(socket, receiver.resubscribe()));
+    }
+}
+
+/// Format a block or error as an event.
+fn event(stat: Result<BlockStats, BlockStatsError>) -> String {
+    let json = stat
+        .map_err(|err| err.to_string())
+        .and_then(|stats| serde_json::to_string(&stats).map_err(|err| err.to_string()));
+    match json {
+        Ok(json) => format!("data: {json}\n\n"),
+        // A line break would end the data field.
+        Err(err) => format!("event: error\ndata: {}\n\n", err.replace('\n', " ")),
+    }
+}
+
+async fn serve_client(mut socket: TcpStream, mut receiver: broadcast::Receiver<String>) {
+    // The request itself doesn't matter but it needs to be read before responding.
+    if read_request_head(&mut socket).await.is_none() {
+        return;
+    }
+    if socket.write_all(RESPONSE_HEAD).await.is_err() {
+        return;
+    }
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                if socket.write_all(event.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+            // We rather skip blocks than slowing down everyone else.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
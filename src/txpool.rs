@@ -0,0 +1,85 @@
+//! Sample the transaction pool of a node independently of block production.
+
+use crate::{display::FmtWith, ticks, BlockStatsError, Bytes, DisplayConfig, DisplayWith};
+use futures::{StreamExt, TryStream};
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+use subxt::backend::rpc::{rpc_params, RpcClient};
+
+/// The content of the transaction pool at a point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxPoolStats {
+    /// When the pool was sampled.
+    pub timestamp: SystemTime,
+    /// Number of extrinsics waiting in the pool.
+    pub num_extrinsics: u64,
+    /// Combined size of all extrinsics in the pool.
+    pub len: Bytes,
+}
+
+impl TxPoolStats {
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
+}
+
+impl fmt::Display for TxPoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for TxPoolStats {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
+        write!(
+            f,
+            "TxPool: NumExtrinsics={:04} Size={}",
+            self.num_extrinsics,
+            config.size(self.len),
+        )
+    }
+}
+
+/// Connect to the specified node and sample its transaction pool every `interval`.
+///
+/// Unlike the block stats this isn't tied to block arrival and hence also shows how the
+/// pool fills up and drains between blocks. The first sample is taken right away. When
+/// a sample takes longer than `interval` the next one is taken immediately instead of
+/// trying to catch up.
+pub async fn tx_pool_stream(
+    url: &str,
+    interval: Duration,
+) -> Result<impl TryStream<Ok = TxPoolStats, Error = BlockStatsError> + Unpin, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    Ok(tx_pool_stream_with_client(rpc_client, interval))
+}
+
+/// Like [`tx_pool_stream`] but using an existing client.
+pub fn tx_pool_stream_with_client(
+    rpc_client: RpcClient,
+    interval: Duration,
+) -> impl TryStream<Ok = TxPoolStats, Error = BlockStatsError> + Unpin {
+    Box::pin(ticks(interval).then(move |()| {
+        let rpc_client = rpc_client.clone();
+        async move { sample(&rpc_client).await }
+    }))
+}
+
+async fn sample(rpc_client: &RpcClient) -> Result<TxPoolStats, BlockStatsError> {
+    let timestamp = SystemTime::now();
+    let pending: Vec<subxt::ext::sp_core::Bytes> = rpc_client
+        .request("author_pendingExtrinsics", rpc_params![])
+        .await?;
+    Ok(TxPoolStats {
+        timestamp,
+        num_extrinsics: pending.len() as u64,
+        len: Bytes(pending.iter().map(|extrinsic| extrinsic.len() as u64).sum()),
+    })
+}
@@ -0,0 +1,162 @@
+//! Weight-only stats for nodes that don't expose `dev_getBlockStats`.
+
+use crate::{
+    display::FmtWith, BlockStatsError, DisplayConfig, DisplayWith, Header, PerDispatchClass,
+    Percent, StatsFetcher, StatsSource, SubxtSource, Weight,
+};
+use futures::{stream::BoxStream, Stream, StreamExt, TryStreamExt};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use subxt::{backend::rpc::RpcClient, ext::sp_core::H256};
+
+/// The weight related subset of [`BlockStats`](crate::BlockStats).
+///
+/// Only needs storage access which is available on public nodes that have the unsafe
+/// `dev_getBlockStats` RPC disabled. Hence nothing about the PoV is known.
+///
+/// Use the custom [`fmt::Display`] implementation to pretty print it.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightStats {
+    /// The block hash.
+    pub hash: H256,
+    /// The block number.
+    pub number: u32,
+    /// The `spec_version` of the runtime that produced this block.
+    pub spec_version: u32,
+    /// Overall weight used by the block.
+    pub weight: Weight,
+    /// The weight used by the block for each dispatch class.
+    ///
+    /// Their sum is [`Self::weight`].
+    pub class_weight: PerDispatchClass<Weight>,
+    /// The maximum allowed weight of the runtime that produced this block.
+    pub max_weight: Weight,
+    /// How long it took to fetch the weight of this block.
+    pub fetch_duration: Duration,
+}
+
+impl WeightStats {
+    /// Used `ref_time` as a percentage of the one in [`Self::max_weight`].
+    pub fn ref_time_percent(&self) -> Percent {
+        Percent::of(self.weight.ref_time, self.max_weight.ref_time)
+    }
+
+    /// Used `proof_size` as a percentage of the one in [`Self::max_weight`].
+    ///
+    /// Always zero on runtimes using the legacy one dimensional weight.
+    pub fn proof_size_percent(&self) -> Percent {
+        Percent::of(self.weight.proof_size, self.max_weight.proof_size)
+    }
+
+    /// Display with the units and precision of `config` instead of the defaults.
+    pub fn fmt_with<'a>(&'a self, config: &'a DisplayConfig) -> DisplayWith<'a, Self> {
+        DisplayWith {
+            value: self,
+            config,
+        }
+    }
+}
+
+impl fmt::Display for WeightStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(&DisplayConfig::default()).fmt(f)
+    }
+}
+
+impl FmtWith for WeightStats {
+    fn write_with(&self, f: &mut fmt::Formatter, config: &DisplayConfig) -> fmt::Result {
+        write!(
+            f,
+            "{:04}{}: Weight RefTime={:07}ms({}%) Weight ProofSize={}({}%)",
+            self.number,
+            config.hash(self.hash),
+            self.weight.ref_time / 1_000_000_000,
+            config.percent(self.ref_time_percent()),
+            config.size(self.weight.proof_size),
+            config.percent(self.proof_size_percent()),
+        )
+    }
+}
+
+/// The weight of consecutive blocks as returned by [`subscribe_weight_stats`].
+///
+/// Like [`BlockStatsStream`](crate::BlockStatsStream) a named [`Stream`] that can be
+/// stored in a struct. It is `Send` and `Unpin`.
+pub struct WeightStatsStream(BoxStream<'static, Result<WeightStats, BlockStatsError>>);
+
+impl WeightStatsStream {
+    /// Wrap any stream of weight stats.
+    pub fn new(
+        stats: impl Stream<Item = Result<WeightStats, BlockStatsError>> + Send + 'static,
+    ) -> Self {
+        Self(stats.boxed())
+    }
+}
+
+impl Stream for WeightStatsStream {
+    type Item = Result<WeightStats, BlockStatsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl fmt::Debug for WeightStatsStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WeightStatsStream").finish_non_exhaustive()
+    }
+}
+
+/// Connect to the specified node and listen for the weight of new blocks.
+///
+/// Like [`subscribe_stats`](crate::subscribe_stats) but works without the
+/// `dev_getBlockStats` RPC. The `url` needs to be a websocket.
+pub async fn subscribe_weight_stats(url: &str) -> Result<WeightStatsStream, BlockStatsError> {
+    let rpc_client = RpcClient::from_url(url).await?;
+    subscribe_weight_stats_with_client(rpc_client).await
+}
+
+/// Like [`subscribe_weight_stats`] but using an existing client.
+pub async fn subscribe_weight_stats_with_client(
+    rpc_client: RpcClient,
+) -> Result<WeightStatsStream, BlockStatsError> {
+    let fetcher = StatsFetcher::new(SubxtSource::new(rpc_client).await?);
+    let blocks = fetcher.source.subscribe_blocks().await?;
+    let stats = blocks.and_then(move |(hash, header)| {
+        let fetcher = fetcher.clone();
+        async move { fetch(&fetcher, hash, &header).await }
+    });
+    Ok(WeightStatsStream::new(stats))
+}
+
+async fn fetch<S: StatsSource>(
+    fetcher: &StatsFetcher<S>,
+    hash: H256,
+    header: &Header,
+) -> Result<WeightStats, BlockStatsError> {
+    let started = Instant::now();
+    let version = fetcher.source.runtime_version(hash, header).await?;
+    let limits = fetcher.limits(hash, header, version).await?;
+    let class_weight = fetcher
+        .source
+        .block_weight_at(hash, header, version)
+        .await?;
+    Ok(WeightStats {
+        hash,
+        number: header.number,
+        spec_version: version.spec_version,
+        weight: class_weight.normal + class_weight.operational + class_weight.mandatory,
+        class_weight,
+        max_weight: limits.block_weights.max_block,
+        fetch_duration: started.elapsed(),
+    })
+}